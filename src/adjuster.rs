@@ -1,14 +1,37 @@
 use nix::libc;
 use procfs::process::Process;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs;
+use std::mem::size_of;
 
-use crate::config::{Config, ProcessConfig};
-use crate::{debug, error, info, warn};
+use crate::cgroup::{self, CgroupManager};
+use crate::config::{
+    CgroupConfig, Config, IoClass, IoniceConfig, ProcessConfig, SchedConfig, SchedPolicy,
+    ThreadConfig,
+};
+use crate::cpulist::CpuList;
+use crate::process::{get_comm_for_thread, get_thread_ids_for_pid, ProcessIdentity};
+use crate::{debug, error, info, trace, warn};
+
+/// `which` value of `ioprio_get`/`ioprio_set` selecting a single process (`linux/ioprio.h`).
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Number of bits the I/O scheduling class is shifted by in an I/O priority value.
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+/// Mask of the priority level (class data) in an I/O priority value.
+const IOPRIO_PRIO_MASK: u32 = (1 << IOPRIO_CLASS_SHIFT) - 1;
 
 /// The `Adjuster` struct is responsible for managing and adjusting the nice values
-/// for processes. It interacts with the system to check the current nice value
-/// of a process and adjust it according to the configuration.
+/// and other scheduling settings for processes. It interacts with the system to check
+/// the current settings of a process and adjust them according to the configuration.
 pub struct Adjuster<'a> {
     _config: &'a Config,
+    cgroups: CgroupManager,
+    /// The names of the rules whose cgroup has been set up.
+    ready_cgroups: RefCell<HashSet<String>>,
 }
 
 impl<'a> Adjuster<'a> {
@@ -22,38 +45,320 @@ impl<'a> Adjuster<'a> {
     ///
     /// Returns a new `Adjuster` instance initialized with the provided configuration.
     pub fn new(config: &'a Config) -> Self {
-        Adjuster { _config: config }
+        Adjuster {
+            _config: config,
+            cgroups: CgroupManager::new(config.cgroup_root()),
+            ready_cgroups: RefCell::new(HashSet::new()),
+        }
     }
 
-    /// This is the main function that checks the current nice value of a process
-    /// and adjusts it if necessary. Logs the process and any issues along the way.
+    /// This is the main function that checks the current settings of a process
+    /// and adjusts them if necessary. Logs the process and any issues along the way.
     ///
     /// # Arguments
     ///
-    /// * `pid` - The process ID (PID) of the process to check and adjust.
-    /// * `process_config` - The `ProcessConfig` object that defines the expected nice value.
+    /// * `identity` - The identity of the process to check and adjust.
+    /// * `process_config` - The `ProcessConfig` object that defines the expected settings.
     ///
     /// # Description
     ///
-    /// This function is responsible for initiating the checking and adjustment of the nice value.
-    /// It logs the start and end of the process, handles any errors, and provides detailed debugging information.
-    pub fn check_and_adjust_nice_value(&self, pid: i32, process_config: &ProcessConfig) {
+    /// This function is responsible for initiating the checking and adjustment of every setting
+    /// configured for the process. Each setting is handled on its own, so a failure to adjust
+    /// one of them does not prevent the others from being applied. Before every change the
+    /// identity of the process is checked again; if the process exited or its PID was reused,
+    /// the remaining settings are skipped.
+    pub fn check_and_adjust(&self, identity: &ProcessIdentity, process_config: &ProcessConfig) {
+        let pid = identity.pid;
         debug!(
-            "Starting check and adjust for PID {} with expected nice value {}",
-            pid, process_config.nice
+            "Starting check and adjust for PID {} with rule '{}'",
+            pid, process_config.name
         );
 
-        if let Err(e) = self.try_check_and_adjust_nice_value(pid, process_config) {
-            warn!(
-                "Failed to check and adjust nice value for PID {}: {}",
-                pid, e
+        self.run_actions(identity, process_config, false);
+
+        debug!(
+            "Finished check and adjust for PID {} with rule '{}'",
+            pid, process_config.name
+        );
+    }
+
+    /// Re-checks the settings of a process that was already adjusted and restores the
+    /// configured values if they drifted away (e.g. the process or an admin reniced it).
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process to check.
+    /// * `process_config` - The `ProcessConfig` object that defines the expected settings.
+    ///
+    /// # Description
+    ///
+    /// Corrections are logged as drift. Errors are only logged at debug level, since the
+    /// process may simply have exited since the last check.
+    pub fn enforce(&self, identity: &ProcessIdentity, process_config: &ProcessConfig) {
+        trace!(
+            "Enforcing rule '{}' for PID {}",
+            process_config.name,
+            identity.pid
+        );
+
+        self.run_actions(identity, process_config, true);
+    }
+
+    /// Checks and adjusts every setting configured for a process, until the process turns
+    /// out to be gone.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The `ProcessConfig` object that defines the expected settings.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process.
+    fn run_actions(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        enforcing: bool,
+    ) {
+        let result = self.try_check_and_adjust_nice_value(
+            identity,
+            process_config,
+            process_config.nice,
+            enforcing,
+        );
+        if !self.report_failure(identity, "nice value", result, enforcing) {
+            return;
+        }
+
+        if let Some(ionice) = &process_config.ionice {
+            let result =
+                self.try_check_and_adjust_ionice(identity, process_config, ionice, enforcing);
+            if !self.report_failure(identity, "I/O priority", result, enforcing) {
+                return;
+            }
+        }
+
+        if let Some(sched) = &process_config.sched {
+            let result =
+                self.try_check_and_adjust_sched(identity, process_config, sched, enforcing);
+            if !self.report_failure(identity, "scheduling policy", result, enforcing) {
+                return;
+            }
+        }
+
+        if let Some(affinity) = &process_config.affinity {
+            let result = self
+                .get_rule_threads(identity.pid, process_config)
+                .and_then(|tids| {
+                    self.try_check_and_adjust_affinity(
+                        identity,
+                        process_config,
+                        affinity,
+                        &tids,
+                        enforcing,
+                    )
+                });
+            if !self.report_failure(identity, "CPU affinity", result, enforcing) {
+                return;
+            }
+        }
+
+        if let Some(oom_score_adj) = process_config.oom_score_adj {
+            let result = self.try_check_and_adjust_oom_score_adj(
+                identity,
+                process_config,
+                oom_score_adj,
+                enforcing,
             );
+            if !self.report_failure(identity, "OOM score adjustment", result, enforcing) {
+                return;
+            }
         }
 
+        if let Some(cgroup) = &process_config.cgroup {
+            let result =
+                self.try_check_and_adjust_cgroup(identity, process_config, cgroup, enforcing);
+            self.report_failure(identity, "cgroup", result, enforcing);
+        }
+    }
+
+    /// Checks the settings of a thread rule for a single thread and adjusts them if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the thread.
+    /// * `process_config` - The rule that matched the process the thread belongs to.
+    /// * `thread` - The thread rule that matched the thread.
+    pub fn check_and_adjust_thread(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        thread: &ThreadConfig,
+    ) {
         debug!(
-            "Finished check and adjust for PID {} with expected nice value {}",
-            pid, process_config.nice
+            "Applying thread rule '{}' of rule '{}' to TID {}",
+            thread.comm, process_config.name, identity.pid
+        );
+
+        self.run_thread_actions(identity, process_config, thread, false);
+    }
+
+    /// Re-checks the settings of a thread that was already adjusted and restores them if
+    /// they drifted.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the thread.
+    /// * `process_config` - The rule that matched the process the thread belongs to.
+    /// * `thread` - The thread rule that matched the thread.
+    pub fn enforce_thread(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        thread: &ThreadConfig,
+    ) {
+        trace!(
+            "Enforcing thread rule '{}' of rule '{}' for TID {}",
+            thread.comm,
+            process_config.name,
+            identity.pid
         );
+
+        self.run_thread_actions(identity, process_config, thread, true);
+    }
+
+    /// Checks and adjusts every setting of a thread rule for a single thread. The kernel
+    /// applies nice values and scheduling policies set for a thread ID to that thread only.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the thread.
+    /// * `process_config` - The rule that matched the process the thread belongs to.
+    /// * `thread` - The thread rule that matched the thread.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted thread.
+    fn run_thread_actions(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        thread: &ThreadConfig,
+        enforcing: bool,
+    ) {
+        if let Some(nice) = thread.nice {
+            let result =
+                self.try_check_and_adjust_nice_value(identity, process_config, nice, enforcing);
+            if !self.report_failure(identity, "nice value", result, enforcing) {
+                return;
+            }
+        }
+
+        if let Some(sched) = &thread.sched {
+            let result =
+                self.try_check_and_adjust_sched(identity, process_config, sched, enforcing);
+            if !self.report_failure(identity, "scheduling policy", result, enforcing) {
+                return;
+            }
+        }
+
+        if let Some(affinity) = &thread.affinity {
+            let result = self.try_check_and_adjust_affinity(
+                identity,
+                process_config,
+                affinity,
+                &[identity.pid],
+                enforcing,
+            );
+            self.report_failure(identity, "CPU affinity", result, enforcing);
+        }
+    }
+
+    /// Logs the failure to check or adjust a setting. A failure caused by the process having
+    /// exited, or its PID having been reused, is not a problem of the setting and is only
+    /// logged at debug level.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `setting` - A human readable name of the setting.
+    /// * `result` - The result of checking and adjusting the setting.
+    /// * `enforcing` - Whether this was a re-check, in which case the failure is only logged
+    ///   at debug level since the process may have exited in the meantime.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the remaining settings should be applied.
+    /// * `false` if the process is gone and the adjustment is aborted.
+    fn report_failure(
+        &self,
+        identity: &ProcessIdentity,
+        setting: &str,
+        result: Result<(), String>,
+        enforcing: bool,
+    ) -> bool {
+        let Err(e) = result else {
+            return true;
+        };
+        let pid = identity.pid;
+        if !identity.is_current() {
+            debug!(
+                "PID {} exited or now belongs to a different process, aborting the adjustment: {}",
+                pid, e
+            );
+            return false;
+        }
+
+        if enforcing {
+            debug!("Failed to enforce {} for PID {}: {}", setting, pid, e);
+        } else {
+            warn!(
+                "Failed to check and adjust {} for PID {}: {}",
+                setting, pid, e
+            );
+        }
+        true
+    }
+
+    /// Changes a setting of a process, checking right before and after the change that the
+    /// PID still belongs to the process that was matched.
+    ///
+    /// The system calls only take a PID, so the process may exit and its PID be reused
+    /// between the first check and the change. A process that is still running after the
+    /// change cannot have given its PID away in between, so the change reached it. Otherwise
+    /// the change may have reached a different process, which is logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `setting` - A human readable name of the setting.
+    /// * `apply` - The change to make.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if the change was made to the matched process or `Err` if
+    /// the change failed, or the process exited or its PID was reused.
+    fn change(
+        &self,
+        identity: &ProcessIdentity,
+        setting: &str,
+        apply: impl FnOnce() -> Result<(), String>,
+    ) -> Result<(), String> {
+        let pid = identity.pid;
+        let gone = || {
+            format!(
+                "PID {} no longer belongs to the process started at {}",
+                pid, identity.starttime
+            )
+        };
+        if !identity.is_current() {
+            return Err(gone());
+        }
+
+        apply()?;
+        if !identity.is_current() {
+            warn!(
+                "PID {} exited while its {} was changed, the change may have reached a process that reused the PID",
+                pid, setting
+            );
+            return Err(gone());
+        }
+        Ok(())
     }
 
     /// Tries to check the current nice value of the process and adjusts it if necessary.
@@ -61,40 +366,392 @@ impl<'a> Adjuster<'a> {
     ///
     /// # Arguments
     ///
-    /// * `pid` - The process ID (PID) of the process.
-    /// * `process_config` - The configuration that contains the expected nice value for the process.
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The configuration of the rule that matched the process.
+    /// * `expected_nice` - The expected nice value.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process, in which case
+    ///   a mismatch is logged as drift.
     ///
     /// # Returns
     ///
     /// A `Result` which is `Ok` if everything was successful or `Err` if an error occurred.
     fn try_check_and_adjust_nice_value(
         &self,
-        pid: i32,
+        identity: &ProcessIdentity,
         process_config: &ProcessConfig,
+        expected_nice: i32,
+        enforcing: bool,
     ) -> Result<(), String> {
+        let pid = identity.pid;
         debug!("Fetching process details for PID {}", pid);
         let process = self.get_process(pid)?;
 
         debug!("Fetching current nice value for PID {}", pid);
         let current_nice = self.get_current_nice_value(&process)?;
 
-        let expected_nice = process_config.nice;
         debug!(
             "Current nice value for PID {}: {}, Expected nice value: {}",
             pid, current_nice, expected_nice
         );
 
         if current_nice != expected_nice {
-            self.log_nice_mismatch(process_config, pid, current_nice, expected_nice);
+            self.log_mismatch(
+                process_config,
+                pid,
+                "nice value",
+                &current_nice,
+                &expected_nice,
+                enforcing,
+            );
             debug!("Adjusting nice value for PID {}", pid);
-            self.adjust_nice_value(pid, expected_nice)?;
+            self.change(identity, "nice value", || {
+                self.adjust_nice_value(pid, expected_nice)
+            })?;
+        } else {
+            self.log_match(process_config, pid, "nice value", &current_nice);
+        }
+
+        Ok(())
+    }
+
+    /// Tries to check the current I/O priority of the process and adjusts it if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The configuration of the rule that matched the process.
+    /// * `ionice` - The expected I/O priority.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if everything was successful or `Err` if an error occurred.
+    fn try_check_and_adjust_ionice(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        ionice: &IoniceConfig,
+        enforcing: bool,
+    ) -> Result<(), String> {
+        let pid = identity.pid;
+        debug!("Fetching current I/O priority for PID {}", pid);
+        let current = self.get_current_ionice(pid)?;
+        let expected = Some(ionice.normalized());
+        debug!(
+            "Current I/O priority for PID {}: {}, Expected I/O priority: {}",
+            pid,
+            format_ionice(&current),
+            ionice
+        );
+
+        if current != expected {
+            self.log_mismatch(
+                process_config,
+                pid,
+                "I/O priority",
+                &format_ionice(&current),
+                ionice,
+                enforcing,
+            );
+            debug!("Adjusting I/O priority for PID {}", pid);
+            self.change(identity, "I/O priority", || self.adjust_ionice(pid, ionice))?;
         } else {
-            self.log_nice_match(process_config, pid, current_nice);
+            self.log_match(process_config, pid, "I/O priority", ionice);
         }
 
         Ok(())
     }
 
+    /// Tries to check the current scheduling policy of the process and adjusts it if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The configuration of the rule that matched the process.
+    /// * `sched` - The expected scheduling policy.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if everything was successful or `Err` if an error occurred.
+    fn try_check_and_adjust_sched(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        sched: &SchedConfig,
+        enforcing: bool,
+    ) -> Result<(), String> {
+        let pid = identity.pid;
+        debug!("Fetching current scheduling policy for PID {}", pid);
+        let current = self.get_current_sched(pid)?;
+        debug!(
+            "Current scheduling policy for PID {}: {}, Expected scheduling policy: {}",
+            pid, current, sched
+        );
+
+        if current != *sched {
+            self.log_mismatch(
+                process_config,
+                pid,
+                "scheduling policy",
+                &current,
+                sched,
+                enforcing,
+            );
+            debug!("Adjusting scheduling policy for PID {}", pid);
+            self.change(identity, "scheduling policy", || {
+                self.adjust_sched(pid, sched)
+            })?;
+        } else {
+            self.log_match(process_config, pid, "scheduling policy", sched);
+        }
+
+        Ok(())
+    }
+
+    /// Tries to check the CPU affinity of the given threads of the process and adjusts the
+    /// threads whose affinity differs from the configured one.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The configuration of the rule that matched the process.
+    /// * `affinity` - The configured CPUs.
+    /// * `tids` - The thread IDs of the threads to check.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if everything was successful or `Err` if an error occurred.
+    ///
+    /// # Description
+    ///
+    /// CPUs that are not online are dropped from the configured list with a warning. If none
+    /// of the configured CPUs is online, the affinity is left untouched.
+    fn try_check_and_adjust_affinity(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        affinity: &CpuList,
+        tids: &[i32],
+        enforcing: bool,
+    ) -> Result<(), String> {
+        let pid = identity.pid;
+        let online = CpuList::online().map_err(|e| format!("{:#}", e))?;
+        let expected = affinity.intersection(&online);
+        if expected.is_empty() {
+            return Err(format!(
+                "None of the configured CPUs {} is online (online: {})",
+                affinity, online
+            ));
+        }
+        if expected != *affinity {
+            warn!(
+                "Rule '{}' lists offline CPUs, using {} instead of {}",
+                process_config.name, expected, affinity
+            );
+        }
+
+        debug!(
+            "Fetching CPU affinity of {} threads of PID {}",
+            tids.len(),
+            pid
+        );
+        let mut mismatched = Vec::new();
+        for &tid in tids {
+            match self.get_current_affinity(tid) {
+                Ok(current) if current == expected => {}
+                Ok(current) => mismatched.push((tid, current)),
+                // The thread may have exited in the meantime.
+                Err(e) => {
+                    trace!("{}", e);
+                }
+            }
+        }
+
+        let Some((_, current)) = mismatched.first() else {
+            self.log_match(process_config, pid, "CPU affinity", &expected);
+            return Ok(());
+        };
+
+        self.log_mismatch(
+            process_config,
+            pid,
+            "CPU affinity",
+            current,
+            &expected,
+            enforcing,
+        );
+        debug!(
+            "Adjusting CPU affinity of {} threads of PID {}",
+            mismatched.len(),
+            pid
+        );
+        let mut adjusted = 0;
+        self.change(identity, "CPU affinity", || {
+            for (tid, _) in &mismatched {
+                if self.adjust_affinity(*tid, &expected)? {
+                    adjusted += 1;
+                }
+            }
+            Ok(())
+        })?;
+        if adjusted > 0 {
+            info!(
+                "Successfully adjusted CPU affinity of {} threads of PID {} to {}",
+                adjusted, pid, expected
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Tries to check the current OOM score adjustment of the process and adjusts it if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The configuration of the rule that matched the process.
+    /// * `expected` - The expected OOM score adjustment.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if everything was successful or `Err` if an error occurred.
+    fn try_check_and_adjust_oom_score_adj(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        expected: i32,
+        enforcing: bool,
+    ) -> Result<(), String> {
+        let pid = identity.pid;
+        debug!("Fetching current OOM score adjustment for PID {}", pid);
+        let current = self.get_current_oom_score_adj(pid)?;
+        debug!(
+            "Current OOM score adjustment for PID {}: {}, Expected OOM score adjustment: {}",
+            pid, current, expected
+        );
+
+        if current != expected {
+            self.log_mismatch(
+                process_config,
+                pid,
+                "OOM score adjustment",
+                &current,
+                &expected,
+                enforcing,
+            );
+            debug!("Adjusting OOM score adjustment for PID {}", pid);
+            self.change(identity, "OOM score adjustment", || {
+                self.adjust_oom_score_adj(pid, expected)
+            })?;
+        } else {
+            self.log_match(process_config, pid, "OOM score adjustment", &current);
+        }
+
+        Ok(())
+    }
+
+    /// Tries to check whether the process is in its configured reniced-managed cgroup and moves
+    /// it there if it is not. The group is created and its resource controls are set the first
+    /// time the rule is applied. Processes of a systemd service are left in place unless the
+    /// cgroup sets `move_services`.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `process_config` - The configuration of the rule that matched the process.
+    /// * `cgroup` - The configured cgroup.
+    /// * `enforcing` - Whether this is a re-check of an already adjusted process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if everything was successful or `Err` if an error occurred.
+    fn try_check_and_adjust_cgroup(
+        &self,
+        identity: &ProcessIdentity,
+        process_config: &ProcessConfig,
+        cgroup: &CgroupConfig,
+        enforcing: bool,
+    ) -> Result<(), String> {
+        let pid = identity.pid;
+        debug!("Fetching current cgroup for PID {}", pid);
+        let current = cgroup::get_cgroup_for_pid(pid).map_err(|e| format!("{:#}", e))?;
+        let expected = self.cgroups.relative_path(&cgroup.name);
+        debug!(
+            "Current cgroup for PID {}: {}, Expected cgroup: {}",
+            pid, current, expected
+        );
+
+        if current != expected && !cgroup.move_services {
+            if let Some(service) = cgroup::service_in_path(&current) {
+                let message = format!(
+                    "Leaving PID {} in the cgroup of {}, set move_services to move processes of services",
+                    pid, service
+                );
+                if enforcing {
+                    debug!("{}", message);
+                } else {
+                    info!("{}", message);
+                }
+                return Ok(());
+            }
+        }
+
+        self.ensure_cgroup(process_config, cgroup)?;
+
+        if current != expected {
+            self.log_mismatch(
+                process_config,
+                pid,
+                "cgroup",
+                &current,
+                &expected,
+                enforcing,
+            );
+            debug!("Moving PID {} into cgroup {}", pid, expected);
+            self.change(identity, "cgroup", || {
+                self.cgroups.move_process(pid, &cgroup.name).map_err(|e| {
+                    let error_message = format!("{:#}", e);
+                    error!("{}", error_message);
+                    error_message
+                })
+            })?;
+        } else {
+            self.log_match(process_config, pid, "cgroup", &current);
+        }
+
+        Ok(())
+    }
+
+    /// Creates and configures the cgroup of a rule unless that was already done.
+    ///
+    /// # Arguments
+    ///
+    /// * `process_config` - The configuration of the rule.
+    /// * `cgroup` - The configured cgroup.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok` if the group is ready or `Err` if it could not be set up.
+    fn ensure_cgroup(
+        &self,
+        process_config: &ProcessConfig,
+        cgroup: &CgroupConfig,
+    ) -> Result<(), String> {
+        if self.ready_cgroups.borrow().contains(&process_config.name) {
+            return Ok(());
+        }
+        self.cgroups
+            .ensure_group(cgroup)
+            .map_err(|e| format!("{:#}", e))?;
+        self.ready_cgroups
+            .borrow_mut()
+            .insert(process_config.name.clone());
+        Ok(())
+    }
+
     /// Retrieves the process for a given PID.
     ///
     /// # Arguments
@@ -127,46 +784,229 @@ impl<'a> Adjuster<'a> {
             .map_err(|_| format!("Could not access stat information for PID {}", process.pid))
     }
 
-    /// Logs a message when there is a mismatch between the current and expected nice values.
+    /// Fetches the current I/O priority of a process using the `ioprio_get` system call.
     ///
     /// # Arguments
     ///
-    /// * `process_config` - The process configuration that contains the expected nice value.
     /// * `pid` - The PID of the process.
-    /// * `current_nice` - The current nice value of the process.
-    /// * `expected_nice` - The expected nice value for the process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the I/O priority, `None` if the process has no explicit
+    /// I/O priority (it is derived from the nice value), or an error message.
+    fn get_current_ionice(&self, pid: i32) -> Result<Option<IoniceConfig>, String> {
+        let result = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid) };
+
+        if result < 0 {
+            return Err(format!(
+                "Could not get I/O priority for PID {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        let value = result as u32;
+        let level = (value & IOPRIO_PRIO_MASK) as u8;
+        let class = match value >> IOPRIO_CLASS_SHIFT {
+            1 => IoClass::Realtime,
+            2 => IoClass::BestEffort,
+            3 => IoClass::Idle,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(IoniceConfig { class, level }.normalized()))
+    }
+
+    /// Fetches the current scheduling policy of a process using the `sched_getscheduler`
+    /// and `sched_getparam` system calls.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the scheduling policy or an error message.
+    fn get_current_sched(&self, pid: i32) -> Result<SchedConfig, String> {
+        let raw_policy = unsafe { libc::sched_getscheduler(pid) };
+        if raw_policy < 0 {
+            return Err(format!(
+                "Could not get scheduling policy for PID {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        let mut param = libc::sched_param { sched_priority: 0 };
+        if unsafe { libc::sched_getparam(pid, &mut param) } < 0 {
+            return Err(format!(
+                "Could not get scheduling parameters for PID {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        let policy = match raw_policy & !libc::SCHED_RESET_ON_FORK {
+            libc::SCHED_OTHER => SchedPolicy::Other,
+            libc::SCHED_BATCH => SchedPolicy::Batch,
+            libc::SCHED_IDLE => SchedPolicy::Idle,
+            libc::SCHED_FIFO => SchedPolicy::Fifo,
+            libc::SCHED_RR => SchedPolicy::Rr,
+            other => {
+                return Err(format!(
+                    "Unsupported scheduling policy {} for PID {}",
+                    other, pid
+                ))
+            }
+        };
+
+        Ok(SchedConfig {
+            policy,
+            rt_priority: param.sched_priority.max(0) as u32,
+            reset_on_fork: raw_policy & libc::SCHED_RESET_ON_FORK != 0,
+        })
+    }
+
+    /// Fetches the current OOM score adjustment of a process from `/proc/{pid}/oom_score_adj`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the OOM score adjustment or an error message.
+    fn get_current_oom_score_adj(&self, pid: i32) -> Result<i32, String> {
+        let path = format!("/proc/{}/oom_score_adj", pid);
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+
+        content
+            .trim()
+            .parse()
+            .map_err(|e| format!("Could not parse {}: {}", path, e))
+    }
+
+    /// Lists the threads of a process that the CPU affinity of its rule applies to: all of
+    /// them, except those matched by a thread rule with an affinity of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `process_config` - The rule that matched the process.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the thread IDs or an error message.
+    fn get_rule_threads(
+        &self,
+        pid: i32,
+        process_config: &ProcessConfig,
+    ) -> Result<Vec<i32>, String> {
+        let tids = get_thread_ids_for_pid(pid).map_err(|e| format!("{:#}", e))?;
+        if process_config.threads.is_empty() {
+            return Ok(tids);
+        }
+
+        Ok(tids
+            .into_iter()
+            .filter(|&tid| {
+                tid == pid
+                    || get_comm_for_thread(pid, tid)
+                        .and_then(|comm| process_config.thread_rule(&comm))
+                        .is_none_or(|thread| thread.affinity.is_none())
+            })
+            .collect())
+    }
+
+    /// Fetches the CPU affinity of a thread using the `sched_getaffinity` system call.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The thread ID.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the CPUs the thread may run on or an error message.
+    fn get_current_affinity(&self, tid: i32) -> Result<CpuList, String> {
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let result =
+            unsafe { libc::sched_getaffinity(tid, size_of::<libc::cpu_set_t>(), &mut set) };
+
+        if result < 0 {
+            return Err(format!(
+                "Could not get CPU affinity for TID {}: {}",
+                tid,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        let cpus =
+            (0..libc::CPU_SETSIZE as usize).filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) });
+        Ok(CpuList::from_cpus(cpus))
+    }
+
+    /// Logs a message when there is a mismatch between the current and expected value of a setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `process_config` - The process configuration that contains the expected value.
+    /// * `pid` - The PID of the process.
+    /// * `setting` - A human readable name of the setting (e.g. `nice value`).
+    /// * `current` - The current value of the setting.
+    /// * `expected` - The expected value of the setting.
+    /// * `enforcing` - Whether the process was already adjusted before, in which case the
+    ///   mismatch is logged as drift.
     ///
     /// # Description
     ///
-    /// This function logs a warning indicating that the current nice value does not match the expected nice value.
-    fn log_nice_mismatch(
+    /// This function logs that the current value does not match the expected value. A mismatch
+    /// found while enforcing is logged as a warning, since something changed the setting behind
+    /// reniced's back.
+    fn log_mismatch(
         &self,
         process_config: &ProcessConfig,
         pid: i32,
-        current_nice: i32,
-        expected_nice: i32,
+        setting: &str,
+        current: &dyn Display,
+        expected: &dyn Display,
+        enforcing: bool,
     ) {
-        info!(
-            "Process '{}' (PID: {}) has a nice value of {} but expected {}. Adjusting...",
-            process_config.name, pid, current_nice, expected_nice
-        );
+        if enforcing {
+            warn!(
+                "Process '{}' (PID: {}) drifted to {} {}, restoring {}",
+                process_config.name, pid, setting, current, expected
+            );
+        } else {
+            info!(
+                "Process '{}' (PID: {}) has {} {} but expected {}. Adjusting...",
+                process_config.name, pid, setting, current, expected
+            );
+        }
     }
 
-    /// Logs a message when the current nice value matches the expected nice value.
+    /// Logs a message when the current value of a setting matches the expected value.
     ///
     /// # Arguments
     ///
-    /// * `process_config` - The process configuration that contains the expected nice value.
+    /// * `process_config` - The process configuration that contains the expected value.
     /// * `pid` - The PID of the process.
-    /// * `current_nice` - The current nice value of the process.
+    /// * `setting` - A human readable name of the setting (e.g. `nice value`).
+    /// * `current` - The current value of the setting.
     ///
     /// # Description
     ///
-    /// This function logs an informational message indicating that the process already has the correct nice value.
-    fn log_nice_match(&self, process_config: &ProcessConfig, pid: i32, current_nice: i32) {
+    /// This function logs a debug message indicating that the process already has the correct value.
+    fn log_match(
+        &self,
+        process_config: &ProcessConfig,
+        pid: i32,
+        setting: &str,
+        current: &dyn Display,
+    ) {
         debug!(
-            "Process '{}' (PID: {}) already has the correct nice value of {}",
-            process_config.name, pid, current_nice
+            "Process '{}' (PID: {}) already has the correct {} of {}",
+            process_config.name, pid, setting, current
         );
     }
 
@@ -210,4 +1050,364 @@ impl<'a> Adjuster<'a> {
             Err(error_message)
         }
     }
+
+    /// Attempts to adjust the I/O priority of a process using the `ioprio_set` system call.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process to adjust.
+    /// * `ionice` - The desired I/O priority.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the I/O priority was successfully adjusted (`Ok`) or if there was an error (`Err`).
+    fn adjust_ionice(&self, pid: i32, ionice: &IoniceConfig) -> Result<(), String> {
+        debug!(
+            "Attempting to set I/O priority for PID {} to {}",
+            pid, ionice
+        );
+
+        let ionice = ionice.normalized();
+        let class: u32 = match ionice.class {
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+        let value = (class << IOPRIO_CLASS_SHIFT) | ionice.level as u32;
+
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                pid,
+                value as libc::c_int,
+            )
+        };
+
+        if result == 0 {
+            info!(
+                "Successfully adjusted I/O priority for PID {} to {}",
+                pid, ionice
+            );
+            Ok(())
+        } else {
+            let error_message = format!(
+                "Failed to adjust I/O priority for PID {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            );
+
+            error!("{}", error_message);
+            Err(error_message)
+        }
+    }
+
+    /// Attempts to adjust the scheduling policy of a process using the `sched_setscheduler`
+    /// system call.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process to adjust.
+    /// * `sched` - The desired scheduling policy.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the scheduling policy was successfully adjusted (`Ok`) or if there was an error (`Err`).
+    fn adjust_sched(&self, pid: i32, sched: &SchedConfig) -> Result<(), String> {
+        debug!(
+            "Attempting to set scheduling policy for PID {} to {}",
+            pid, sched
+        );
+
+        let mut policy = match sched.policy {
+            SchedPolicy::Other => libc::SCHED_OTHER,
+            SchedPolicy::Batch => libc::SCHED_BATCH,
+            SchedPolicy::Idle => libc::SCHED_IDLE,
+            SchedPolicy::Fifo => libc::SCHED_FIFO,
+            SchedPolicy::Rr => libc::SCHED_RR,
+        };
+        if sched.reset_on_fork {
+            policy |= libc::SCHED_RESET_ON_FORK;
+        }
+        let param = libc::sched_param {
+            sched_priority: sched.rt_priority as libc::c_int,
+        };
+
+        let result = unsafe { libc::sched_setscheduler(pid, policy, &param) };
+
+        if result == 0 {
+            info!(
+                "Successfully adjusted scheduling policy for PID {} to {}",
+                pid, sched
+            );
+            Ok(())
+        } else {
+            let error_message = format!(
+                "Failed to adjust scheduling policy for PID {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            );
+
+            error!("{}", error_message);
+            Err(error_message)
+        }
+    }
+
+    /// Attempts to adjust the CPU affinity of a thread using the `sched_setaffinity` system call.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - The thread ID to adjust.
+    /// * `affinity` - The CPUs the thread may run on.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is `Ok(true)` if the CPU affinity was adjusted, `Ok(false)` if the
+    /// thread exited in the meantime, or `Err` if there was an error.
+    fn adjust_affinity(&self, tid: i32, affinity: &CpuList) -> Result<bool, String> {
+        debug!(
+            "Attempting to set CPU affinity for TID {} to {}",
+            tid, affinity
+        );
+
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for cpu in affinity.iter() {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(format!("CPU {} does not fit into an affinity mask", cpu));
+            }
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+
+        let result = unsafe { libc::sched_setaffinity(tid, size_of::<libc::cpu_set_t>(), &set) };
+
+        if result == 0 {
+            Ok(true)
+        } else {
+            let error = std::io::Error::last_os_error();
+            // Threads come and go, one that exited since it was listed is not an error.
+            if error.raw_os_error() == Some(libc::ESRCH) {
+                trace!("TID {} exited before its CPU affinity was adjusted", tid);
+                return Ok(false);
+            }
+            let error_message = format!("Failed to adjust CPU affinity for TID {}: {}", tid, error);
+
+            error!("{}", error_message);
+            Err(error_message)
+        }
+    }
+
+    /// Attempts to adjust the OOM score adjustment of a process by writing
+    /// `/proc/{pid}/oom_score_adj`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process to adjust.
+    /// * `oom_score_adj` - The desired OOM score adjustment.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the OOM score adjustment was successfully adjusted (`Ok`) or if there was an error (`Err`).
+    ///
+    /// # Description
+    ///
+    /// Lowering the value below the current one requires `CAP_SYS_RESOURCE`.
+    fn adjust_oom_score_adj(&self, pid: i32, oom_score_adj: i32) -> Result<(), String> {
+        debug!(
+            "Attempting to set OOM score adjustment for PID {} to {}",
+            pid, oom_score_adj
+        );
+
+        let path = format!("/proc/{}/oom_score_adj", pid);
+        match fs::write(&path, oom_score_adj.to_string()) {
+            Ok(()) => {
+                info!(
+                    "Successfully adjusted OOM score adjustment for PID {} to {}",
+                    pid, oom_score_adj
+                );
+                Ok(())
+            }
+            Err(e) => {
+                let error_message = format!(
+                    "Failed to adjust OOM score adjustment for PID {}: {}",
+                    pid, e
+                );
+
+                error!("{}", error_message);
+                Err(error_message)
+            }
+        }
+    }
+}
+
+/// Formats an I/O priority read from the kernel, which may be unset.
+///
+/// # Arguments
+///
+/// * `ionice` - The I/O priority, or `None` if it is derived from the nice value.
+///
+/// # Returns
+///
+/// * A `String` such as `best-effort/4` or `none`.
+fn format_ionice(ionice: &Option<IoniceConfig>) -> String {
+    match ionice {
+        Some(ionice) => ionice.to_string(),
+        None => String::from("none"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::get_starttime_for_pid;
+    use std::cell::Cell;
+    use std::process::{Child, Command};
+
+    /// Helper function to start a process that can be adjusted and killed.
+    fn sleeper() -> (Child, ProcessIdentity) {
+        let child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = child.id() as i32;
+        let identity = ProcessIdentity::open(pid, get_starttime_for_pid(pid).unwrap()).unwrap();
+        (child, identity)
+    }
+
+    /// Tests that a change is only made while the matched process is running, and that a
+    /// process exiting during the change is reported as an error.
+    #[test]
+    fn test_change_checks_identity() {
+        let config = Config::default();
+        let adjuster = Adjuster::new(&config);
+
+        let (mut child, identity) = sleeper();
+        let applied = Cell::new(false);
+        let apply = || {
+            applied.set(true);
+            Ok(())
+        };
+        assert!(adjuster.change(&identity, "nice value", apply).is_ok());
+        assert!(applied.get());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        applied.set(false);
+        assert!(adjuster.change(&identity, "nice value", apply).is_err());
+        assert!(!applied.get());
+
+        let (mut child, identity) = sleeper();
+        let result = adjuster.change(&identity, "nice value", || {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            Ok(())
+        });
+        assert!(result.is_err());
+    }
+
+    /// Tests that a change is reported as an error if the process exits during the change
+    /// and a new process is started in its place, for identities with and without a pidfd.
+    #[test]
+    fn test_change_detects_reused_pid() {
+        let config = Config::default();
+        let adjuster = Adjuster::new(&config);
+
+        for with_pidfd in [true, false] {
+            let (mut child, identity) = sleeper();
+            let identity = if with_pidfd {
+                identity
+            } else {
+                ProcessIdentity::thread(identity.pid, identity.starttime)
+            };
+            let mut replacement = None;
+            let result = adjuster.change(&identity, "nice value", || {
+                child.kill().unwrap();
+                child.wait().unwrap();
+                replacement = Some(sleeper().0);
+                Ok(())
+            });
+            assert!(result.is_err());
+
+            let mut replacement = replacement.unwrap();
+            replacement.kill().unwrap();
+            replacement.wait().unwrap();
+        }
+    }
+
+    /// Tests that a thread identity whose start time no longer matches the thread ID is not
+    /// changed.
+    #[test]
+    fn test_change_rejects_stale_thread_identity() {
+        let config = Config::default();
+        let adjuster = Adjuster::new(&config);
+
+        let (mut child, identity) = sleeper();
+        let stale = ProcessIdentity::thread(identity.pid, identity.starttime + 1);
+        let applied = Cell::new(false);
+        let result = adjuster.change(&stale, "nice value", || {
+            applied.set(true);
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert!(!applied.get());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    /// Tests that a failure only aborts the remaining settings once the process is gone.
+    #[test]
+    fn test_report_failure_aborts_when_gone() {
+        let config = Config::default();
+        let adjuster = Adjuster::new(&config);
+
+        let (mut child, identity) = sleeper();
+        assert!(adjuster.report_failure(&identity, "nice value", Ok(()), false));
+        assert!(adjuster.report_failure(&identity, "nice value", Err(String::from("EPERM")), true));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!adjuster.report_failure(
+            &identity,
+            "nice value",
+            Err(String::from("ESRCH")),
+            false
+        ));
+    }
+
+    /// Tests that the cgroup of a rule is only set up the first time the rule is applied.
+    #[test]
+    fn test_ensure_cgroup_once() {
+        let root = std::env::temp_dir().join(format!("reniced-adjuster-{}", std::process::id()));
+        let config: Config =
+            serde_yaml::from_str(&format!("cgroup_root: {}\nprocess: []\n", root.display()))
+                .unwrap();
+        let adjuster = Adjuster::new(&config);
+        let rule = ProcessConfig {
+            name: String::from("builds"),
+            ..Default::default()
+        };
+        let cgroup = CgroupConfig {
+            name: String::from("builds"),
+            ..Default::default()
+        };
+
+        assert!(adjuster.ensure_cgroup(&rule, &cgroup).is_ok());
+        assert!(root.join("reniced/builds").is_dir());
+        fs::remove_dir_all(&root).unwrap();
+        assert!(adjuster.ensure_cgroup(&rule, &cgroup).is_ok());
+        assert!(!root.exists());
+    }
+
+    /// Tests that a thread which exited before its CPU affinity could be set is skipped
+    /// instead of failing the whole adjustment.
+    #[test]
+    fn test_adjust_affinity_skips_exited_thread() {
+        let config = Config::default();
+        let adjuster = Adjuster::new(&config);
+        let affinity = CpuList::parse("0").unwrap();
+
+        let (mut child, identity) = sleeper();
+        assert_eq!(adjuster.adjust_affinity(identity.pid, &affinity), Ok(true));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(adjuster.adjust_affinity(identity.pid, &affinity), Ok(false));
+    }
 }
@@ -0,0 +1,502 @@
+use anyhow::{bail, Context, Result};
+use nix::libc;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::CgroupConfig;
+use crate::{debug, info, trace};
+
+/// The default mount point of the cgroup v2 hierarchy.
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The name of the group below the cgroup root that holds all reniced-managed groups.
+const MANAGED_GROUP: &str = "reniced";
+
+/// The period of `cpu.max` in microseconds the kernel uses unless one is written.
+const DEFAULT_CPU_PERIOD: &str = "100000";
+
+/// Manages the reniced subgroups of a cgroup v2 hierarchy.
+///
+/// All managed groups live in `<root>/reniced/<name>`. The root is configurable so the
+/// manager can operate on a fake directory tree.
+pub struct CgroupManager {
+    root: PathBuf,
+}
+
+impl CgroupManager {
+    /// Creates a new `CgroupManager` for the hierarchy mounted at `root`.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The path the cgroup v2 hierarchy is mounted at.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `CgroupManager`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        CgroupManager { root: root.into() }
+    }
+
+    /// Returns the path of a managed group relative to the cgroup root, the way it shows up
+    /// in `/proc/{pid}/cgroup`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the managed group.
+    ///
+    /// # Returns
+    ///
+    /// * A `String` such as `/reniced/batch`.
+    pub fn relative_path(&self, name: &str) -> String {
+        format!("/{}/{}", MANAGED_GROUP, name)
+    }
+
+    /// Creates a managed group if necessary, enables the controllers it needs and sets its
+    /// resource limits. Limits are normalized to the form the kernel reads them back in, and
+    /// those that already have the configured value are not written.
+    ///
+    /// # Arguments
+    ///
+    /// * `cgroup` - The configuration of the group.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the group is ready to receive processes.
+    /// * `Err(anyhow::Error)` if the group could not be created or configured.
+    pub fn ensure_group(&self, cgroup: &CgroupConfig) -> Result<()> {
+        let managed = self.root.join(MANAGED_GROUP);
+        let group = managed.join(&cgroup.name);
+
+        if !group.is_dir() {
+            debug!("Creating cgroup {}", group.display());
+            fs::create_dir_all(&group)
+                .with_context(|| format!("Failed to create cgroup {}", group.display()))?;
+        }
+
+        let controllers = cgroup.controllers();
+        enable_controllers(&self.root, &controllers)?;
+        enable_controllers(&managed, &controllers)?;
+
+        if let Some(weight) = cgroup.cpu_weight {
+            write_if_changed(&group, "cpu.weight", &weight.to_string())?;
+        }
+        if let Some(cpu_max) = &cgroup.cpu_max {
+            write_if_changed(&group, "cpu.max", &normalize_cpu_max(cpu_max))?;
+        }
+        if let Some(weight) = cgroup.io_weight {
+            write_if_changed(&group, "io.weight", &format!("default {}", weight))?;
+        }
+        if let Some(memory_high) = &cgroup.memory_high {
+            write_if_changed(&group, "memory.high", &normalize_memory(memory_high)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves a process into a managed group by writing its PID to `cgroup.procs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `name` - The name of the managed group.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the process was moved.
+    /// * `Err(anyhow::Error)` if writing `cgroup.procs` failed.
+    pub fn move_process(&self, pid: i32, name: &str) -> Result<()> {
+        let procs = self
+            .root
+            .join(MANAGED_GROUP)
+            .join(name)
+            .join("cgroup.procs");
+        fs::write(&procs, pid.to_string())
+            .with_context(|| format!("Failed to write PID {} to {}", pid, procs.display()))?;
+        info!("Moved PID {} into cgroup {}", pid, self.relative_path(name));
+        Ok(())
+    }
+}
+
+/// Reads the cgroup v2 path of a process from `/proc/{pid}/cgroup`.
+///
+/// # Arguments
+///
+/// * `pid` - The PID of the process.
+///
+/// # Returns
+///
+/// * `Ok(String)` containing the path relative to the cgroup root, e.g. `/user.slice`.
+/// * `Err(anyhow::Error)` if the file could not be read or has no cgroup v2 entry.
+pub fn get_cgroup_for_pid(pid: i32) -> Result<String> {
+    let path = format!("/proc/{}/cgroup", pid);
+    let content = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?;
+    parse_cgroup_v2_path(&content)
+        .with_context(|| format!("No cgroup v2 entry in {}", path))
+        .map(str::to_string)
+}
+
+/// Extracts the cgroup v2 path from the contents of `/proc/{pid}/cgroup`.
+///
+/// # Arguments
+///
+/// * `content` - The contents of the file.
+///
+/// # Returns
+///
+/// * `Some(&str)` with the path of the unified hierarchy entry (`0::<path>`).
+/// * `None` if there is no such entry.
+pub fn parse_cgroup_v2_path(content: &str) -> Option<&str> {
+    content.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Returns the systemd units a cgroup path belongs to, from the outermost slice to the
+/// innermost service or scope.
+///
+/// # Arguments
+///
+/// * `path` - The cgroup v2 path, e.g. `/system.slice/sshd.service`.
+///
+/// # Returns
+///
+/// * An iterator over the path components that are unit names.
+pub fn units_in_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|component| {
+        [".service", ".scope", ".slice"]
+            .iter()
+            .any(|suffix| component.ends_with(suffix))
+    })
+}
+
+/// Returns the systemd service a cgroup path belongs to, if its innermost unit is a service.
+/// Processes in a scope or slice below a service, such as the apps of a user session below
+/// `user@1000.service`, do not belong to that service.
+///
+/// # Arguments
+///
+/// * `path` - The cgroup v2 path, e.g. `/system.slice/sshd.service`.
+///
+/// # Returns
+///
+/// * `Some(&str)` with the name of the service.
+/// * `None` if the innermost unit is not a service, or there is none.
+pub fn service_in_path(path: &str) -> Option<&str> {
+    units_in_path(path)
+        .last()
+        .filter(|unit| unit.ends_with(".service"))
+}
+
+/// Enables controllers for the children of a cgroup, skipping those already enabled.
+///
+/// # Arguments
+///
+/// * `group` - The path of the parent cgroup.
+/// * `controllers` - The controllers the children need.
+///
+/// # Returns
+///
+/// * `Ok(())` if all controllers are enabled.
+/// * `Err(anyhow::Error)` if `cgroup.subtree_control` could not be written.
+fn enable_controllers(group: &Path, controllers: &[&str]) -> Result<()> {
+    let path = group.join("cgroup.subtree_control");
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    let enabled: Vec<&str> = current.split_whitespace().collect();
+
+    let missing: Vec<String> = controllers
+        .iter()
+        .filter(|controller| !enabled.contains(controller))
+        .map(|controller| format!("+{}", controller))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    debug!(
+        "Enabling controllers {} in {}",
+        missing.join(" "),
+        path.display()
+    );
+    fs::write(&path, missing.join(" "))
+        .with_context(|| format!("Failed to enable controllers in {}", path.display()))
+}
+
+/// Writes a cgroup interface file unless it already contains the given value.
+/// The kernel may report more than was written (e.g. per-device lines in `io.weight`), so
+/// only the fields present in `value` are compared.
+///
+/// # Arguments
+///
+/// * `group` - The path of the cgroup.
+/// * `file` - The name of the interface file.
+/// * `value` - The value to write.
+///
+/// # Returns
+///
+/// * `Ok(())` if the file has the value.
+/// * `Err(anyhow::Error)` if writing failed.
+fn write_if_changed(group: &Path, file: &str, value: &str) -> Result<()> {
+    let path = group.join(file);
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    let mut current_fields = current.split_whitespace();
+    if value
+        .split_whitespace()
+        .all(|field| current_fields.next() == Some(field))
+    {
+        trace!("{} already set to {}", path.display(), value);
+        return Ok(());
+    }
+
+    debug!("Setting {} to {}", path.display(), value);
+    fs::write(&path, value).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Normalizes a `cpu.max` value to `<quota|max> <period>`, the form the kernel reads it back
+/// in. A value without a period gets the default period, so `max` becomes `max 100000`.
+///
+/// # Arguments
+///
+/// * `value` - The configured value.
+///
+/// # Returns
+///
+/// * A `String` with both fields.
+pub fn normalize_cpu_max(value: &str) -> String {
+    let mut fields = value.split_whitespace();
+    let quota = fields.next().unwrap_or("max");
+    let period = fields.next().unwrap_or(DEFAULT_CPU_PERIOD);
+    format!("{} {}", quota, period)
+}
+
+/// Parses a memory size such as `512M` or `2G` into bytes. The suffixes `K`, `M`, `G` and
+/// `T` (in either case) stand for powers of 1024, as in cgroup interface files.
+///
+/// # Arguments
+///
+/// * `value` - The size.
+///
+/// # Returns
+///
+/// * `Some(u64)` with the size in bytes.
+/// * `None` if the size is malformed or does not fit into 64 bits.
+pub fn parse_size(value: &str) -> Option<u64> {
+    let (number, shift) = match value.char_indices().last()? {
+        (index, 'K' | 'k') => (&value[..index], 10),
+        (index, 'M' | 'm') => (&value[..index], 20),
+        (index, 'G' | 'g') => (&value[..index], 30),
+        (index, 'T' | 't') => (&value[..index], 40),
+        _ => (value, 0),
+    };
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// Normalizes a memory limit to the form the kernel reads it back in: `max`, or the size in
+/// bytes rounded down to whole pages.
+///
+/// # Arguments
+///
+/// * `value` - The configured value, `max` or a size like `2G`.
+///
+/// # Returns
+///
+/// * `Ok(String)` with the normalized value.
+/// * `Err(anyhow::Error)` if the size is malformed.
+fn normalize_memory(value: &str) -> Result<String> {
+    if value == "max" {
+        return Ok(value.to_string());
+    }
+    let bytes = parse_size(value).with_context(|| format!("Invalid memory size '{}'", value))?;
+    let page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    };
+    Ok((bytes / page_size * page_size).to_string())
+}
+
+/// Checks that a managed group name is a single, plain path component.
+///
+/// # Arguments
+///
+/// * `name` - The name to check.
+///
+/// # Returns
+///
+/// * `Ok(())` if the name is valid.
+/// * `Err(anyhow::Error)` otherwise.
+pub fn validate_group_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        bail!("Invalid cgroup name '{}'", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper function to create an empty fake cgroup root in the temporary directory.
+    fn fake_root(test: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("reniced-cgroup-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("cgroup.subtree_control"), "memory").unwrap();
+        root
+    }
+
+    /// Tests that `ensure_group` creates the group, enables only the missing controllers and
+    /// writes the configured limits.
+    #[test]
+    fn test_ensure_group_creates_and_configures() {
+        let root = fake_root("ensure");
+        let manager = CgroupManager::new(&root);
+        let cgroup = CgroupConfig {
+            name: String::from("batch"),
+            cpu_weight: Some(50),
+            io_weight: Some(20),
+            cpu_max: Some(String::from("50000 100000")),
+            memory_high: Some(String::from("2G")),
+            move_services: false,
+        };
+
+        manager.ensure_group(&cgroup).unwrap();
+
+        let group = root.join("reniced/batch");
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&root.join("cgroup.subtree_control")), "+cpu +io");
+        assert_eq!(
+            read(&root.join("reniced/cgroup.subtree_control")),
+            "+cpu +io +memory"
+        );
+        assert_eq!(read(&group.join("cpu.weight")), "50");
+        assert_eq!(read(&group.join("cpu.max")), "50000 100000");
+        assert_eq!(read(&group.join("io.weight")), "default 20");
+        assert_eq!(read(&group.join("memory.high")), "2147483648");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Tests that limits the kernel reads back in a different form than configured are
+    /// recognized as unchanged and not written again.
+    #[test]
+    fn test_ensure_group_compares_normalized_values() {
+        let root = fake_root("normalize");
+        let manager = CgroupManager::new(&root);
+        let cgroup = CgroupConfig {
+            name: String::from("batch"),
+            cpu_max: Some(String::from("max")),
+            memory_high: Some(String::from("2G")),
+            ..Default::default()
+        };
+        let group = root.join("reniced/batch");
+        fs::create_dir_all(&group).unwrap();
+        fs::write(group.join("cpu.max"), "max 100000\n").unwrap();
+        fs::write(group.join("memory.high"), "2147483648\n").unwrap();
+
+        manager.ensure_group(&cgroup).unwrap();
+
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&group.join("cpu.max")), "max 100000\n");
+        assert_eq!(read(&group.join("memory.high")), "2147483648\n");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Tests that `cpu.max` values get the default period and sizes are parsed into bytes.
+    #[test]
+    fn test_normalize_values() {
+        assert_eq!(normalize_cpu_max("max"), "max 100000");
+        assert_eq!(normalize_cpu_max("50000"), "50000 100000");
+        assert_eq!(normalize_cpu_max("50000 200000"), "50000 200000");
+
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("512k"), Some(512 << 10));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+        assert_eq!(parse_size("1T"), Some(1 << 40));
+        assert_eq!(parse_size("2GG"), None);
+        assert_eq!(parse_size("G"), None);
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("99999999999T"), None);
+
+        assert_eq!(normalize_memory("max").unwrap(), "max");
+        assert!(normalize_memory("lots").is_err());
+    }
+
+    /// Tests that `move_process` writes the PID into the group's `cgroup.procs`.
+    #[test]
+    fn test_move_process() {
+        let root = fake_root("move");
+        let manager = CgroupManager::new(&root);
+        let cgroup = CgroupConfig {
+            name: String::from("idle"),
+            ..Default::default()
+        };
+
+        manager.ensure_group(&cgroup).unwrap();
+        manager.move_process(1234, "idle").unwrap();
+
+        let procs = fs::read_to_string(root.join("reniced/idle/cgroup.procs")).unwrap();
+        assert_eq!(procs, "1234");
+        assert_eq!(manager.relative_path("idle"), "/reniced/idle");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Tests that the unified hierarchy entry is found among legacy v1 entries.
+    #[test]
+    fn test_parse_cgroup_v2_path() {
+        let content = "12:cpu,cpuacct:/legacy\n0::/user.slice/user-1000.slice\n";
+        assert_eq!(
+            parse_cgroup_v2_path(content),
+            Some("/user.slice/user-1000.slice")
+        );
+        assert_eq!(parse_cgroup_v2_path("1:name=systemd:/\n"), None);
+    }
+
+    /// Tests that unit names are picked from a user session path, and plain directories
+    /// below a unit are skipped.
+    #[test]
+    fn test_units_in_path() {
+        let path = "/user.slice/user-1000.slice/user@1000.service/app.slice/app-flatpak-org.foo-12.scope/sub";
+        assert_eq!(
+            units_in_path(path).collect::<Vec<_>>(),
+            vec![
+                "user.slice",
+                "user-1000.slice",
+                "user@1000.service",
+                "app.slice",
+                "app-flatpak-org.foo-12.scope"
+            ]
+        );
+        assert_eq!(units_in_path("/").count(), 0);
+    }
+
+    /// Tests that only processes whose innermost unit is a service belong to one.
+    #[test]
+    fn test_service_in_path() {
+        assert_eq!(
+            service_in_path("/system.slice/sshd.service"),
+            Some("sshd.service")
+        );
+        assert_eq!(
+            service_in_path(
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/pipewire.service"
+            ),
+            Some("pipewire.service")
+        );
+        assert_eq!(
+            service_in_path(
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/app-foo-1.scope"
+            ),
+            None
+        );
+        assert_eq!(service_in_path("/reniced/builds"), None);
+    }
+
+    /// Tests that group names cannot escape the managed group.
+    #[test]
+    fn test_validate_group_name() {
+        assert!(validate_group_name("batch").is_ok());
+        assert!(validate_group_name("").is_err());
+        assert!(validate_group_name("..").is_err());
+        assert!(validate_group_name("a/b").is_err());
+    }
+}
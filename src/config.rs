@@ -1,12 +1,26 @@
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use anyhow::{bail, Context, Result};
+use nix::unistd::{Group, User};
+use regex::Regex;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::cgroup;
+use crate::container::Runtime;
+use crate::cpulist::CpuList;
 use crate::{debug, trace, warn};
 
+/// The path of the system-wide configuration file.
+const GLOBAL_CONFIG_PATH: &str = "/etc/reniced/config.yaml";
+
 /// Represents the configuration for a single process.
 /// This configuration includes details like the process name, owner, binary path, nice value,
 /// and matching configuration.
@@ -14,26 +28,1085 @@ use crate::{debug, trace, warn};
 pub struct ProcessConfig {
     /// The name of the process.
     pub name: String,
-    /// The owner of the process (optional).
-    pub owner: Option<String>,
+    /// The user and group that must own the process (optional).
+    pub owner: Option<OwnerMatcher>,
     /// The path to the binary of the process.
     pub bin: String,
     /// The nice value to set for the process.
     pub nice: i32,
+    /// The I/O priority to set for the process (optional).
+    pub ionice: Option<IoniceConfig>,
+    /// The scheduling policy to set for the process (optional).
+    pub sched: Option<SchedConfig>,
+    /// The CPUs the process may run on, in cpulist syntax such as `0-3,8` (optional).
+    pub affinity: Option<CpuList>,
+    /// The OOM killer score adjustment from -1000 to 1000 (optional).
+    #[serde(default, deserialize_with = "deserialize_oom_score_adj")]
+    pub oom_score_adj: Option<i32>,
+    /// The reniced-managed cgroup to move the process into (optional).
+    pub cgroup: Option<CgroupConfig>,
     /// The configuration for matching the process.
     pub matcher: MatcherConfig,
+    /// How often the settings are re-applied after the process was first adjusted.
+    #[serde(default)]
+    pub enforce: EnforcePolicy,
+    /// Whether the rule applies to the matched process only or to its whole subtree.
+    #[serde(default)]
+    pub scope: Scope,
+    /// The precedence of the rule when several rules match a process; higher wins.
+    #[serde(default)]
+    pub priority: i32,
+    /// Rules for single threads of the process, selected by thread name (optional).
+    #[serde(default)]
+    pub threads: Vec<ThreadConfig>,
+    /// Whether the rule applies to kernel threads instead of user space processes.
+    #[serde(default)]
+    pub kernel_thread: bool,
+    /// Whether the rule may adjust processes on the `protect` list.
+    #[serde(default)]
+    pub allow_protected: bool,
+    /// The configuration file the rule was loaded from.
+    #[serde(skip)]
+    pub source: RuleSource,
+    /// The position of the rule in its configuration file.
+    #[serde(skip)]
+    pub order: usize,
+}
+
+impl ProcessConfig {
+    /// Returns the number of conditions a process must meet to match the rule. Used to
+    /// prefer the more specific of two rules with the same priority.
+    ///
+    /// # Returns
+    ///
+    /// * The specificity of the rule's owner and matcher.
+    pub fn specificity(&self) -> usize {
+        usize::from(self.owner.is_some()) + self.matcher.specificity()
+    }
+
+    /// Returns the option of the rule that only the global configuration may set, since it
+    /// lets a rule adjust processes outside of the user's control.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&str)` with the name of the option if the rule sets `allow_protected` or
+    ///   `kernel_thread`.
+    /// * `None` otherwise.
+    fn global_only_option(&self) -> Option<&'static str> {
+        if self.allow_protected {
+            Some("allow_protected")
+        } else if self.kernel_thread {
+            Some("kernel_thread")
+        } else {
+            None
+        }
+    }
+
+    /// Returns the thread rule for a thread name. The first matching thread rule wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `comm` - The name of the thread from `/proc/{pid}/task/{tid}/comm`.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&ThreadConfig)` with the first thread rule whose glob matches the name.
+    /// * `None` if no thread rule matches.
+    pub fn thread_rule(&self, comm: &str) -> Option<&ThreadConfig> {
+        self.threads.iter().find(|thread| {
+            thread
+                .compiled
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(comm))
+        })
+    }
+}
+
+/// Represents the settings for the threads of a matched process whose name matches a glob.
+/// Threads are adjusted on their own, by thread ID; the main thread is left to the rule.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ThreadConfig {
+    /// A glob the thread name must match, e.g. `AudioThread*`. The kernel truncates thread
+    /// names to 15 bytes.
+    pub comm: String,
+    /// The nice value to set for the thread (optional).
+    pub nice: Option<i32>,
+    /// The scheduling policy to set for the thread (optional).
+    pub sched: Option<SchedConfig>,
+    /// The CPUs the thread may run on, in cpulist syntax (optional).
+    pub affinity: Option<CpuList>,
+    /// The compiled `comm` glob, set by `compile`.
+    #[serde(skip)]
+    pub compiled: Option<Regex>,
+}
+
+impl ThreadConfig {
+    /// Compiles the `comm` glob and validates the settings of the thread rule.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the thread rule is valid.
+    /// * `Err(anyhow::Error)` if the glob is invalid, the rule sets nothing, or its
+    ///   scheduling policy is invalid.
+    fn compile(&mut self) -> Result<()> {
+        let regex = Regex::new(&glob_to_regex(&self.comm))
+            .with_context(|| format!("Invalid thread comm glob '{}'", self.comm))?;
+        self.compiled = Some(regex);
+        if self.nice.is_none() && self.sched.is_none() && self.affinity.is_none() {
+            bail!(
+                "thread rule '{}' sets neither nice, sched nor affinity",
+                self.comm
+            );
+        }
+        if let Some(sched) = &self.sched {
+            sched.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// The configuration file a rule was loaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RuleSource {
+    /// The system-wide configuration in `/etc/reniced`.
+    #[default]
+    Global,
+    /// The configuration in a user's home directory.
+    User(String),
+}
+
+impl fmt::Display for RuleSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleSource::Global => f.write_str("global configuration"),
+            RuleSource::User(user) => write!(f, "configuration of user {}", user),
+        }
+    }
+}
+
+/// Represents the I/O priority (`ionice`) to set for a process.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoniceConfig {
+    /// The I/O scheduling class.
+    pub class: IoClass,
+    /// The priority level within the class, from 0 (highest) to 7 (lowest).
+    /// Ignored for the `idle` class.
+    #[serde(
+        default = "default_ionice_level",
+        deserialize_with = "deserialize_ionice_level"
+    )]
+    pub level: u8,
+}
+
+/// The I/O scheduling classes supported by the kernel.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Always gets first access to the disk. Requires `CAP_SYS_ADMIN` or `CAP_SYS_NICE`.
+    Realtime,
+    /// The default class for processes with an explicit I/O priority.
+    BestEffort,
+    /// Only gets disk time when no other process needs it.
+    Idle,
+}
+
+impl IoniceConfig {
+    /// Returns the I/O priority the way the kernel reports it back, i.e. with the level
+    /// cleared for the `idle` class, so it can be compared with the current value.
+    pub fn normalized(&self) -> Self {
+        match self.class {
+            IoClass::Idle => IoniceConfig {
+                class: IoClass::Idle,
+                level: 0,
+            },
+            _ => *self,
+        }
+    }
+}
+
+impl fmt::Display for IoniceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.class {
+            IoClass::Realtime => write!(f, "realtime/{}", self.level),
+            IoClass::BestEffort => write!(f, "best-effort/{}", self.level),
+            IoClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Represents the scheduling policy to set for a process.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedConfig {
+    /// The scheduling policy.
+    pub policy: SchedPolicy,
+    /// The real-time priority from 1 to 99. Required for `fifo` and `rr`, must be
+    /// omitted (or 0) for the other policies.
+    #[serde(default)]
+    pub rt_priority: u32,
+    /// Whether children of the process are reset to `other` with a non-negative nice value.
+    #[serde(default)]
+    pub reset_on_fork: bool,
+}
+
+/// The scheduling policies supported by the kernel.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`).
+    #[serde(alias = "normal")]
+    Other,
+    /// Time-sharing for CPU-bound, non-interactive work (`SCHED_BATCH`).
+    Batch,
+    /// Only runs when nothing else wants the CPU (`SCHED_IDLE`).
+    Idle,
+    /// Real-time first-in first-out (`SCHED_FIFO`).
+    Fifo,
+    /// Real-time round-robin (`SCHED_RR`).
+    Rr,
+}
+
+impl SchedConfig {
+    /// Checks that the real-time priority fits the policy.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the configuration is valid.
+    /// * `Err(anyhow::Error)` describing the problem otherwise.
+    pub fn validate(&self) -> Result<()> {
+        match self.policy {
+            SchedPolicy::Fifo | SchedPolicy::Rr => {
+                if !(1..=99).contains(&self.rt_priority) {
+                    bail!(
+                        "sched rt_priority must be between 1 and 99 for policy {}, got {}",
+                        self.policy,
+                        self.rt_priority
+                    );
+                }
+            }
+            _ => {
+                if self.rt_priority != 0 {
+                    bail!(
+                        "sched rt_priority is only valid for fifo and rr, not for policy {}",
+                        self.policy
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SchedPolicy::Other => "other",
+            SchedPolicy::Batch => "batch",
+            SchedPolicy::Idle => "idle",
+            SchedPolicy::Fifo => "fifo",
+            SchedPolicy::Rr => "rr",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for SchedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.policy)?;
+        if self.rt_priority != 0 {
+            write!(f, "/{}", self.rt_priority)?;
+        }
+        if self.reset_on_fork {
+            write!(f, " (reset-on-fork)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents a reniced-managed cgroup v2 group and the resource controls set on it.
+/// Processes are moved into `<cgroup_root>/reniced/<name>`, which takes them out of the
+/// cgroup systemd put them in. Processes of a systemd service are left in place unless
+/// `move_services` is set, since systemd would no longer account, limit or stop them.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CgroupConfig {
+    /// The name of the group below `reniced`.
+    pub name: String,
+    /// The relative CPU weight from 1 to 10000 (`cpu.weight`, optional).
+    pub cpu_weight: Option<u32>,
+    /// The relative I/O weight from 1 to 10000 (`io.weight`, optional).
+    pub io_weight: Option<u32>,
+    /// The CPU bandwidth limit as `"<quota> <period>"` or `"max"` (`cpu.max`, optional).
+    pub cpu_max: Option<String>,
+    /// The memory throttling threshold such as `"2G"` or `"max"` (`memory.high`, optional).
+    pub memory_high: Option<String>,
+    /// Whether processes of a systemd `.service` unit are moved as well.
+    #[serde(default)]
+    pub move_services: bool,
+}
+
+impl CgroupConfig {
+    /// Returns the controllers that must be enabled for the configured resource controls.
+    pub fn controllers(&self) -> Vec<&'static str> {
+        let mut controllers = Vec::new();
+        if self.cpu_weight.is_some() || self.cpu_max.is_some() {
+            controllers.push("cpu");
+        }
+        if self.io_weight.is_some() {
+            controllers.push("io");
+        }
+        if self.memory_high.is_some() {
+            controllers.push("memory");
+        }
+        controllers
+    }
+
+    /// Checks the group name and the format of every configured resource control.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the configuration is valid.
+    /// * `Err(anyhow::Error)` describing the problem otherwise.
+    pub fn validate(&self) -> Result<()> {
+        cgroup::validate_group_name(&self.name)?;
+
+        for (file, weight) in [
+            ("cpu_weight", self.cpu_weight),
+            ("io_weight", self.io_weight),
+        ] {
+            if let Some(weight) = weight {
+                if !(1..=10000).contains(&weight) {
+                    bail!(
+                        "cgroup {} must be between 1 and 10000, got {}",
+                        file,
+                        weight
+                    );
+                }
+            }
+        }
+
+        if let Some(cpu_max) = &self.cpu_max {
+            let fields: Vec<&str> = cpu_max.split_whitespace().collect();
+            let valid = match fields.as_slice() {
+                [quota] => *quota == "max" || quota.parse::<u64>().is_ok(),
+                [quota, period] => {
+                    (*quota == "max" || quota.parse::<u64>().is_ok())
+                        && period.parse::<u64>().is_ok()
+                }
+                _ => false,
+            };
+            if !valid {
+                bail!(
+                    "cgroup cpu_max must be '<quota|max> [period]', got '{}'",
+                    cpu_max
+                );
+            }
+        }
+
+        if let Some(memory_high) = &self.memory_high {
+            if memory_high != "max" && cgroup::parse_size(memory_high).is_none() {
+                bail!(
+                    "cgroup memory_high must be 'max' or a size like '512M', got '{}'",
+                    memory_high
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Describes whether and how often reniced re-checks a matched process and restores its
+/// configured settings if they drifted (e.g. the process reniced itself).
+///
+/// In YAML this is written as `once`, `always` or `interval: 30s`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EnforcePolicy {
+    /// Adjust the process only when it is discovered or `exec`s.
+    #[default]
+    Once,
+    /// Re-check the process on every enforcement pass.
+    Always,
+    /// Re-check the process after the given time has passed.
+    Interval(Duration),
+}
+
+/// The processes a rule applies to once it matched a process.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Only the matched process.
+    #[default]
+    Process,
+    /// The matched process and all of its current and future descendants, until the
+    /// matched process exits. Descendants matched by a rule of their own follow that rule.
+    Subtree,
 }
 
 /// Represents the configuration used to match a process.
 /// This includes details on how the process is identified.
 #[derive(Deserialize, Serialize, Debug, Default)]
 pub struct MatcherConfig {
-    /// The type of matching (e.g., "exact", "regex", etc.).
+    /// The type of matching (e.g., "exact", "regex", etc.). May be left out if the matcher
+    /// has other criteria.
+    #[serde(default)]
     pub r#type: String,
     /// The string to match against the process (optional).
     pub match_string: Option<String>,
     /// Whether to strip the path from the binary name before matching (optional).
     pub strip_path: Option<bool>,
+    /// The program name `argv[0]` must equal (optional). Without a `/` only the base name of
+    /// `argv[0]` is compared.
+    pub argv0: Option<String>,
+    /// The argument `arg_regex` is matched against, `1` being the first argument (optional).
+    pub arg_index: Option<usize>,
+    /// Arguments that must all be present verbatim (optional).
+    pub args_contain: Option<Vec<String>>,
+    /// A regular expression an argument must match (optional). Without `arg_index`, any
+    /// argument may match.
+    pub arg_regex: Option<String>,
+    /// The script, module or Java main class an interpreter must run (optional). Without a
+    /// `/` only the base name of the script is compared.
+    pub script: Option<String>,
+    /// The user and group that must own the process (optional).
+    pub owner: Option<OwnerMatcher>,
+    /// Matchers that must all match the process (optional).
+    pub all: Option<Vec<MatcherConfig>>,
+    /// Matchers of which at least one must match the process (optional).
+    pub any: Option<Vec<MatcherConfig>>,
+    /// A matcher that must not match the process (optional).
+    pub not: Option<Box<MatcherConfig>>,
+    /// A glob the cgroup v2 path of the process must match, e.g. `/user.slice/*` (optional).
+    pub cgroup: Option<String>,
+    /// A regular expression the cgroup v2 path of the process must match (optional).
+    pub cgroup_regex: Option<String>,
+    /// A glob one of the systemd units (services, scopes or slices) in the cgroup path of
+    /// the process must match, e.g. `app-flatpak-org.foo*.scope` (optional).
+    pub unit: Option<String>,
+    /// Environment variables the process must have (optional). Maps each name to a glob its
+    /// value must match, or to `~` if any value will do.
+    pub env: Option<BTreeMap<String, Option<String>>>,
+    /// The container the process must run in (optional).
+    pub container: Option<ContainerMatcher>,
+    /// A matcher the parent of the process must match (optional).
+    pub parent: Option<Box<MatcherConfig>>,
+    /// A matcher one of the ancestors of the process must match (optional).
+    pub ancestor: Option<Box<MatcherConfig>>,
+    /// The compiled `match_string` for pattern based matcher types, set by `compile`.
+    #[serde(skip)]
+    pub compiled: Option<Regex>,
+    /// The compiled `arg_regex`, set by `compile`.
+    #[serde(skip)]
+    pub compiled_arg_regex: Option<Regex>,
+    /// The compiled `cgroup` or `cgroup_regex`, set by `compile`.
+    #[serde(skip)]
+    pub compiled_cgroup: Option<Regex>,
+    /// The compiled `unit` glob, set by `compile`.
+    #[serde(skip)]
+    pub compiled_unit: Option<Regex>,
+    /// The variable names and compiled value globs of `env`, set by `compile`.
+    #[serde(skip)]
+    pub compiled_env: Vec<(String, Option<Regex>)>,
+    /// The canonical executable path for the `exe` matcher type, set by `compile`.
+    #[serde(skip)]
+    pub exe: Option<PathBuf>,
+}
+
+impl MatcherConfig {
+    /// Compiles the pattern of pattern based matcher types, so matching a process never has
+    /// to parse it again. Nested matchers are compiled as well.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the matcher needs no pattern or its pattern is valid.
+    /// * `Err(anyhow::Error)` if the pattern is missing or invalid, or a matcher, `all` or
+    ///   `any` has no criteria.
+    pub fn compile(&mut self) -> Result<()> {
+        // An empty `all` would match every process and an empty `any` none.
+        if self.all.as_ref().is_some_and(Vec::is_empty) {
+            bail!("'all' needs at least one matcher");
+        }
+        if self.any.as_ref().is_some_and(Vec::is_empty) {
+            bail!("'any' needs at least one matcher");
+        }
+        if self.leaf_count() == 0
+            && self.all.is_none()
+            && self.any.is_none()
+            && self.parent.is_none()
+            && self.ancestor.is_none()
+        {
+            bail!("matcher has no criteria");
+        }
+        for child in self.all.iter_mut().chain(self.any.iter_mut()).flatten() {
+            child.compile()?;
+        }
+        if let Some(owner) = &mut self.owner {
+            owner.resolve();
+        }
+        if let Some(not) = &mut self.not {
+            not.compile().context("Invalid matcher in 'not'")?;
+        }
+        if let Some(parent) = &mut self.parent {
+            parent.compile().context("Invalid matcher in 'parent'")?;
+        }
+        if let Some(ancestor) = &mut self.ancestor {
+            ancestor
+                .compile()
+                .context("Invalid matcher in 'ancestor'")?;
+        }
+
+        if let Some(pattern) = &self.arg_regex {
+            let regex =
+                Regex::new(pattern).with_context(|| format!("Invalid arg_regex '{}'", pattern))?;
+            self.compiled_arg_regex = Some(regex);
+        } else if self.arg_index.is_some() {
+            bail!("arg_index requires an arg_regex");
+        }
+        if self.arg_index == Some(0) {
+            bail!("arg_index starts at 1, use argv0 to match the program name");
+        }
+
+        self.compiled_cgroup = match (&self.cgroup, &self.cgroup_regex) {
+            (Some(_), Some(_)) => bail!("cgroup and cgroup_regex cannot be combined"),
+            (Some(glob), None) => Some(
+                Regex::new(&glob_to_regex(glob))
+                    .with_context(|| format!("Invalid cgroup glob '{}'", glob))?,
+            ),
+            (None, Some(pattern)) => Some(
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid cgroup_regex '{}'", pattern))?,
+            ),
+            (None, None) => None,
+        };
+        if let Some(container) = &mut self.container {
+            container.compile()?;
+        }
+        self.compiled_env = Vec::new();
+        for (name, glob) in self.env.iter().flatten() {
+            let value = match glob {
+                Some(glob) => Some(
+                    Regex::new(&glob_to_regex(glob))
+                        .with_context(|| format!("Invalid env glob '{}' for {}", glob, name))?,
+                ),
+                None => None,
+            };
+            self.compiled_env.push((name.clone(), value));
+        }
+        if let Some(glob) = &self.unit {
+            let regex = Regex::new(&glob_to_regex(glob))
+                .with_context(|| format!("Invalid unit glob '{}'", glob))?;
+            self.compiled_unit = Some(regex);
+        }
+
+        match self.r#type.as_str() {
+            "argv" if !self.has_argv_options() => {
+                bail!("argv matcher requires argv0, args_contain, arg_regex or script");
+            }
+            "regex" => {
+                let pattern = self
+                    .match_string
+                    .as_deref()
+                    .context("regex matcher requires a match_string")?;
+                let regex =
+                    Regex::new(pattern).with_context(|| format!("Invalid regex '{}'", pattern))?;
+                self.compiled = Some(regex);
+            }
+            "glob" | "comm_glob" => {
+                let pattern = self
+                    .match_string
+                    .as_deref()
+                    .with_context(|| format!("{} matcher requires a match_string", self.r#type))?;
+                let regex = Regex::new(&glob_to_regex(pattern))
+                    .with_context(|| format!("Invalid glob '{}'", pattern))?;
+                self.compiled = Some(regex);
+            }
+            "exe" => {
+                let path = self
+                    .match_string
+                    .as_deref()
+                    .map(Path::new)
+                    .context("exe matcher requires a match_string")?;
+                if !path.is_absolute() {
+                    bail!(
+                        "exe matcher requires an absolute path, got '{}'",
+                        path.display()
+                    );
+                }
+                // Symlinks such as /usr/bin/python3 are resolved, the kernel reports the
+                // target. A binary that is not installed yet is compared as written.
+                self.exe = Some(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+            }
+            "comm" => {
+                if let Some(comm) = &self.match_string {
+                    check_comm_len(comm)?;
+                }
+            }
+            "" | "argv" | "simple" | "exact" => {}
+            other => bail!(
+                "Unknown matcher type '{}', expected one of: {}",
+                other,
+                MATCHER_TYPES.join(", ")
+            ),
+        }
+        Ok(())
+    }
+
+    /// Checks the `comm` matchers without a `match_string`, at any depth. They compare the
+    /// `bin` of their rule, which is not known while a matcher is compiled.
+    ///
+    /// # Arguments
+    ///
+    /// * `bin` - The `bin` value of the rule.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if `bin` can be a command name, or no matcher falls back to it.
+    /// * `Err(anyhow::Error)` if `bin` is a path or too long to ever match.
+    pub fn check_bin_fallback(&self, bin: &str) -> Result<()> {
+        if self.r#type == "comm" && self.match_string.is_none() {
+            if bin.contains('/') {
+                bail!(
+                    "comm matcher compares the bin '{}', which is a path and can never match; set a match_string with the command name",
+                    bin
+                );
+            }
+            check_comm_len(bin)?;
+        }
+        let nested = [&self.not, &self.parent, &self.ancestor];
+        for child in self
+            .all
+            .iter()
+            .chain(self.any.iter())
+            .flatten()
+            .chain(nested.into_iter().flatten().map(|child| child.as_ref()))
+        {
+            child.check_bin_fallback(bin)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of conditions a process must meet to match this matcher: one per
+    /// type, owner, argument option and `not`, all conditions of every `all` child and of the
+    /// `parent` and `ancestor` matchers, and the fewest conditions of any `any` child.
+    ///
+    /// # Returns
+    ///
+    /// * The specificity of the matcher.
+    pub fn specificity(&self) -> usize {
+        let all: usize = self
+            .all
+            .iter()
+            .flatten()
+            .chain(self.parent.as_deref())
+            .chain(self.ancestor.as_deref())
+            .map(Self::specificity)
+            .sum();
+        let any = self
+            .any
+            .iter()
+            .flatten()
+            .map(Self::specificity)
+            .min()
+            .unwrap_or(0);
+        self.leaf_count() + all + any
+    }
+
+    /// Returns the number of conditions set directly on this matcher, without nested ones.
+    fn leaf_count(&self) -> usize {
+        let leaves = [
+            !self.r#type.is_empty() && self.r#type != "argv",
+            self.owner.is_some(),
+            self.argv0.is_some(),
+            self.args_contain.is_some(),
+            self.arg_regex.is_some(),
+            self.script.is_some(),
+            self.not.is_some(),
+            self.cgroup.is_some(),
+            self.cgroup_regex.is_some(),
+            self.unit.is_some(),
+            self.container.is_some(),
+            self.env.is_some(),
+        ];
+        leaves.iter().filter(|&&set| set).count()
+    }
+
+    /// Returns `true` if any of the argument vector options is set.
+    pub fn has_argv_options(&self) -> bool {
+        self.argv0.is_some()
+            || self.args_contain.is_some()
+            || self.arg_regex.is_some()
+            || self.script.is_some()
+    }
+}
+
+/// Describes the container a process must run in. All options are optional; without any,
+/// every containerized process matches.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ContainerMatcher {
+    /// The runtime that started the container (`podman`, `docker`, `containerd` or `cri-o`).
+    pub runtime: Option<Runtime>,
+    /// A prefix of the container ID.
+    pub id: Option<String>,
+    /// A glob the container name must match, e.g. `ci-*`.
+    pub name_glob: Option<String>,
+    /// The compiled `name_glob`, set by `compile`.
+    #[serde(skip)]
+    pub compiled_name: Option<Regex>,
+}
+
+impl ContainerMatcher {
+    /// Compiles the `name_glob` of the matcher.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the glob is valid or not set.
+    /// * `Err(anyhow::Error)` if the glob is invalid.
+    fn compile(&mut self) -> Result<()> {
+        if let Some(glob) = &self.name_glob {
+            let regex = Regex::new(&glob_to_regex(glob))
+                .with_context(|| format!("Invalid container name_glob '{}'", glob))?;
+            self.compiled_name = Some(regex);
+        }
+        Ok(())
+    }
+}
+
+/// Describes the user and group a process must run as. Written as a user alone
+/// (`alice`, `1000` or `1000-1999`), or as a map with `user`, `group` and `uid`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OwnerMatcher {
+    /// The user, UID or UID range the process must run as (optional).
+    pub user: Option<UserSpec>,
+    /// The group the process must be a member of, as primary or supplementary group
+    /// (optional).
+    pub group: Option<GroupSpec>,
+    /// Whether the real or the effective UID and GID are compared.
+    pub uid: UidKind,
+    /// The UIDs `user` stands for, set by `resolve`. `None` if the user does not exist.
+    pub resolved_uids: Option<RangeInclusive<u32>>,
+    /// The GID `group` stands for, set by `resolve`. `None` if the group does not exist.
+    pub resolved_gid: Option<u32>,
+}
+
+/// A user given by name, UID or inclusive UID range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserSpec {
+    Name(String),
+    Uid(u32),
+    Range(u32, u32),
+}
+
+/// A group given by name or GID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupSpec {
+    Name(String),
+    Gid(u32),
+}
+
+/// Which of the IDs of a process an `OwnerMatcher` compares.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UidKind {
+    /// The real UID and GID, i.e. the user who started the process.
+    #[default]
+    Real,
+    /// The effective UID and GID, i.e. the user whose permissions the process has
+    /// (differs from the real one for setuid programs).
+    Effective,
+}
+
+impl OwnerMatcher {
+    /// Creates an `OwnerMatcher` for the real UID of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user.
+    ///
+    /// # Returns
+    ///
+    /// * A new, unresolved instance of `OwnerMatcher`.
+    pub fn user(user: &str) -> Self {
+        OwnerMatcher {
+            user: Some(UserSpec::Name(user.to_string())),
+            ..Default::default()
+        }
+    }
+
+    /// Resolves the user and group names of the matcher to IDs. A name that does not
+    /// exist is logged and never matches; in particular it is never taken for root.
+    pub fn resolve(&mut self) {
+        self.resolved_uids = match &self.user {
+            Some(UserSpec::Name(name)) => match User::from_name(name) {
+                Ok(Some(user)) => Some(user.uid.as_raw()..=user.uid.as_raw()),
+                _ => {
+                    warn!("Unknown user '{}' in owner matcher, it never matches", name);
+                    None
+                }
+            },
+            Some(UserSpec::Uid(uid)) => Some(*uid..=*uid),
+            Some(UserSpec::Range(first, last)) => Some(*first..=*last),
+            None => None,
+        };
+        self.resolved_gid = match &self.group {
+            Some(GroupSpec::Name(name)) => match Group::from_name(name) {
+                Ok(Some(group)) => Some(group.gid.as_raw()),
+                _ => {
+                    warn!(
+                        "Unknown group '{}' in owner matcher, it never matches",
+                        name
+                    );
+                    None
+                }
+            },
+            Some(GroupSpec::Gid(gid)) => Some(*gid),
+            None => None,
+        };
+    }
+}
+
+impl UserSpec {
+    /// Parses a user as written in the configuration: a UID range such as `1000-1999`, a
+    /// UID or a name.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The user as written.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(UserSpec)` for the user.
+    /// * `Err(anyhow::Error)` if a UID range ends before it starts.
+    fn parse(value: &str) -> Result<Self> {
+        if let Ok(uid) = value.parse() {
+            return Ok(UserSpec::Uid(uid));
+        }
+        let range = value
+            .split_once('-')
+            .and_then(|(first, last)| Some((first.parse().ok()?, last.parse().ok()?)));
+        match range {
+            Some((first, last)) if first > last => {
+                bail!("UID range '{}' ends before it starts", value)
+            }
+            Some((first, last)) => Ok(UserSpec::Range(first, last)),
+            None => Ok(UserSpec::Name(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for UserSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UserSpec::Name(name) => f.write_str(name),
+            UserSpec::Uid(uid) => write!(f, "{}", uid),
+            UserSpec::Range(first, last) => write!(f, "{}-{}", first, last),
+        }
+    }
+}
+
+/// The maximum length of a process command name; the kernel truncates longer names.
+const MAX_COMM_LEN: usize = 15;
+
+/// Checks that a command name fits into the kernel's limit.
+///
+/// # Arguments
+///
+/// * `comm` - The command name a `comm` matcher compares.
+///
+/// # Returns
+///
+/// * `Ok(())` if the name can match.
+/// * `Err(anyhow::Error)` if it is longer than the kernel allows and can never match.
+fn check_comm_len(comm: &str) -> Result<()> {
+    if comm.len() > MAX_COMM_LEN {
+        bail!(
+            "comm '{}' is longer than {} bytes and can never match",
+            comm,
+            MAX_COMM_LEN
+        );
+    }
+    Ok(())
+}
+
+/// The matcher types a rule can use.
+const MATCHER_TYPES: [&str; 8] = [
+    "simple",
+    "exact",
+    "regex",
+    "glob",
+    "exe",
+    "comm",
+    "comm_glob",
+    "argv",
+];
+
+/// Translates a shell-style glob into an anchored regular expression.
+/// `*` matches any sequence of characters (including `/`), `?` matches a single character
+/// and `[...]` (or `[!...]`) matches a character class. An unterminated `[` is literal.
+///
+/// # Arguments
+///
+/// * `glob` - The glob pattern.
+///
+/// # Returns
+///
+/// * A `String` containing the equivalent regular expression.
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::from("^");
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut start = i + 1;
+                let negated = chars.get(start) == Some(&'!');
+                if negated {
+                    start += 1;
+                }
+                // A `]` right after the opening bracket belongs to the class.
+                let end = (start + 1..chars.len()).find(|&j| chars[j] == ']');
+                match end {
+                    Some(end) => {
+                        regex.push('[');
+                        if negated {
+                            regex.push('^');
+                        }
+                        for &c in &chars[start..end] {
+                            if matches!(c, '\\' | '[' | ']' | '^' | '&' | '~') {
+                                regex.push('\\');
+                            }
+                            regex.push(c);
+                        }
+                        regex.push(']');
+                        i = end;
+                    }
+                    None => regex.push_str(r"\["),
+                }
+            }
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        i += 1;
+    }
+
+    regex.push('$');
+    regex
+}
+
+/// The processes that rules must not adjust unless they set `allow_protected: true`.
+/// reniced itself is always protected. Every list that is configured replaces its
+/// built-in default.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct ProtectConfig {
+    /// Protected PIDs.
+    pub pids: Vec<i32>,
+    /// The absolute paths of protected executables.
+    pub exe: Vec<PathBuf>,
+    /// Globs matching the systemd units of protected processes, e.g. `gdm.service`.
+    pub units: Vec<String>,
+    /// Globs matching the names of protected kernel threads, e.g. `migration/*`.
+    pub kernel_threads: Vec<String>,
+    /// The canonical `exe` paths, set by `compile`.
+    #[serde(skip)]
+    pub compiled_exe: Vec<PathBuf>,
+    /// The compiled `units` globs, set by `compile`.
+    #[serde(skip)]
+    pub compiled_units: Vec<Regex>,
+    /// The compiled `kernel_threads` globs, set by `compile`.
+    #[serde(skip)]
+    pub compiled_kernel_threads: Vec<Regex>,
+}
+
+impl Default for ProtectConfig {
+    fn default() -> Self {
+        let mut protect = ProtectConfig {
+            pids: vec![1],
+            exe: [
+                "/usr/lib/systemd/systemd",
+                "/usr/lib/systemd/systemd-journald",
+                "/usr/lib/systemd/systemd-logind",
+                "/usr/lib/systemd/systemd-udevd",
+                "/usr/bin/Xorg",
+                "/usr/lib/xorg/Xorg",
+                "/usr/libexec/Xorg",
+                "/usr/bin/Xwayland",
+                "/usr/bin/gnome-shell",
+                "/usr/bin/kwin_wayland",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect(),
+            units: [
+                "init.scope",
+                "systemd-journald.service",
+                "systemd-logind.service",
+                "systemd-udevd.service",
+                "dbus.service",
+                "dbus-broker.service",
+                "display-manager.service",
+                "gdm.service",
+                "sddm.service",
+                "lightdm.service",
+                "reniced.service",
+            ]
+            .iter()
+            .map(|unit| unit.to_string())
+            .collect(),
+            kernel_threads: [
+                "kthreadd",
+                "migration/*",
+                "rcu_*",
+                "cpuhp/*",
+                "watchdog*",
+                "idle_inject/*",
+                "kauditd",
+            ]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+            compiled_exe: Vec::new(),
+            compiled_units: Vec::new(),
+            compiled_kernel_threads: Vec::new(),
+        };
+        // The built-in paths are absolute and the built-in globs valid, so this cannot fail.
+        let _ = protect.compile();
+        protect
+    }
+}
+
+impl ProtectConfig {
+    /// Resolves the protected executables and compiles the unit and kernel thread globs.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if every entry is valid.
+    /// * `Err(anyhow::Error)` if a path is not absolute or a glob is invalid.
+    fn compile(&mut self) -> Result<()> {
+        let mut compiled_exe = Vec::new();
+        for path in &self.exe {
+            if !path.is_absolute() {
+                bail!(
+                    "protected exe must be an absolute path, got '{}'",
+                    path.display()
+                );
+            }
+            compiled_exe.push(fs::canonicalize(path).unwrap_or_else(|_| path.clone()));
+        }
+
+        let compile_globs = |globs: &[String], what: &str| -> Result<Vec<Regex>> {
+            globs
+                .iter()
+                .map(|glob| {
+                    Regex::new(&glob_to_regex(glob))
+                        .with_context(|| format!("Invalid protected {} glob '{}'", what, glob))
+                })
+                .collect()
+        };
+        self.compiled_units = compile_globs(&self.units, "unit")?;
+        self.compiled_kernel_threads = compile_globs(&self.kernel_threads, "kernel thread")?;
+        self.compiled_exe = compiled_exe;
+        Ok(())
+    }
 }
 
 /// Represents the overall configuration, which consists of a list of process configurations.
@@ -41,6 +1114,12 @@ pub struct MatcherConfig {
 pub struct Config {
     /// A list of process configurations.
     pub process: Vec<ProcessConfig>,
+    /// The mount point of the cgroup v2 hierarchy (optional, global configuration only).
+    /// Defaults to `/sys/fs/cgroup`.
+    pub cgroup_root: Option<PathBuf>,
+    /// The processes rules must not adjust (global configuration only).
+    #[serde(default)]
+    pub protect: ProtectConfig,
 }
 
 impl Config {
@@ -51,7 +1130,7 @@ impl Config {
     /// * `Ok(Config)` containing the global configuration if successful.
     /// * `Err(anyhow::Error)` if an error occurs during file reading or parsing.
     pub fn load_global() -> Result<Self> {
-        let path = Path::new("/etc/reniced/config.yaml");
+        let path = Path::new(GLOBAL_CONFIG_PATH);
         trace!("Loading global configuration from {}", path.display());
         let config =
             Self::load_config_from_file(path).context("Failed to load global configuration")?;
@@ -70,7 +1149,11 @@ impl Config {
     pub fn load_all() -> Result<Self> {
         trace!("Loading all configurations (global and local)");
         let global_config = Self::load_global().unwrap_or_else(|err| {
-            debug!("Failed to load global configuration: {}", err);
+            if Path::new(GLOBAL_CONFIG_PATH).exists() {
+                warn!("Failed to load global configuration: {:#}", err);
+            } else {
+                debug!("Failed to load global configuration: {}", err);
+            }
             Config::default()
         });
 
@@ -138,21 +1221,77 @@ impl Config {
         let mut content = String::new();
         file.read_to_string(&mut content)?;
         trace!("Successfully read file: {}", path.display());
-        let config: Config = serde_yaml::from_str(&content)
+        let mut config: Config = serde_yaml::from_str(&content)
             .with_context(|| format!("Failed to parse YAML file: {}", path.display()))?;
         trace!(
             "Successfully parsed configuration from file: {}",
             path.display()
         );
+        config
+            .prepare()
+            .with_context(|| format!("Invalid configuration in file: {}", path.display()))?;
         Ok(config)
     }
 
+    /// Validates constraints that span several fields of a rule and cannot be expressed
+    /// while parsing the YAML, and compiles the matcher patterns of every rule.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if every rule is valid.
+    /// * `Err(anyhow::Error)` naming the first invalid rule.
+    fn prepare(&mut self) -> Result<()> {
+        self.protect
+            .compile()
+            .context("Invalid protect configuration")?;
+        for (order, process) in self.process.iter_mut().enumerate() {
+            process.order = order;
+            if let Some(owner) = &mut process.owner {
+                owner.resolve();
+            }
+            process
+                .matcher
+                .compile()
+                .and_then(|()| process.matcher.check_bin_fallback(&process.bin))
+                .with_context(|| format!("Invalid matcher in rule '{}'", process.name))?;
+            if let Some(sched) = &process.sched {
+                sched
+                    .validate()
+                    .with_context(|| format!("Invalid rule '{}'", process.name))?;
+            }
+            if let Some(cgroup) = &process.cgroup {
+                cgroup
+                    .validate()
+                    .with_context(|| format!("Invalid rule '{}'", process.name))?;
+            }
+            for thread in &mut process.threads {
+                thread
+                    .compile()
+                    .with_context(|| format!("Invalid thread rule in rule '{}'", process.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the mount point of the cgroup v2 hierarchy.
+    ///
+    /// # Returns
+    ///
+    /// * The configured `cgroup_root`, or `/sys/fs/cgroup` if none is set.
+    pub fn cgroup_root(&self) -> &Path {
+        self.cgroup_root
+            .as_deref()
+            .unwrap_or(Path::new(cgroup::DEFAULT_CGROUP_ROOT))
+    }
+
     /// Merges two configurations: the global configuration and the local configuration.
     ///
     /// This function combines the process configurations from both global and local configs.
     /// If a process with the same name exists in both, the local configuration overwrites
     /// the corresponding fields in the global configuration. Processes present only in the local
-    /// configuration are added to the merged result.
+    /// configuration are added to the merged result. Daemon-wide settings such as
+    /// `cgroup_root` and `protect` are only taken from the global configuration, and user
+    /// rules setting `allow_protected` or `kernel_thread` are ignored with a warning.
     ///
     /// # Arguments
     ///
@@ -167,6 +1306,15 @@ impl Config {
         let mut merged_config = global;
 
         for local_process in local.process {
+            if let RuleSource::User(user) = &local_process.source {
+                if let Some(option) = local_process.global_only_option() {
+                    warn!(
+                        "Ignoring rule '{}' of user {}, {} may only be set in the global configuration",
+                        local_process.name, user, option
+                    );
+                    continue;
+                }
+            }
             if let Some(existing_process) = merged_config
                 .process
                 .iter_mut()
@@ -178,7 +1326,20 @@ impl Config {
                 );
                 existing_process.owner = local_process.owner;
                 existing_process.nice = local_process.nice;
+                existing_process.ionice = local_process.ionice;
+                existing_process.sched = local_process.sched;
+                existing_process.affinity = local_process.affinity;
+                existing_process.oom_score_adj = local_process.oom_score_adj;
+                existing_process.cgroup = local_process.cgroup;
                 existing_process.matcher = local_process.matcher;
+                existing_process.enforce = local_process.enforce;
+                existing_process.scope = local_process.scope;
+                existing_process.priority = local_process.priority;
+                existing_process.threads = local_process.threads;
+                existing_process.kernel_thread = local_process.kernel_thread;
+                existing_process.allow_protected = local_process.allow_protected;
+                existing_process.source = local_process.source;
+                existing_process.order = local_process.order;
             } else {
                 trace!("Adding new process configuration: {}", local_process.name);
                 merged_config.process.push(local_process);
@@ -236,8 +1397,11 @@ fn load_and_prepare_local_config(user: &str) -> Result<Config> {
     for process in &mut local_config.process {
         if process.owner.is_none() {
             trace!("Setting owner for process {} to {}", process.name, user);
-            process.owner = Some(user.to_string());
+            let mut owner = OwnerMatcher::user(user);
+            owner.resolve();
+            process.owner = Some(owner);
         }
+        process.source = RuleSource::User(user.to_string());
     }
     debug!(
         "Successfully prepared local configuration for user: {}",
@@ -245,3 +1409,689 @@ fn load_and_prepare_local_config(user: &str) -> Result<Config> {
     );
     Ok(local_config)
 }
+
+impl Serialize for EnforcePolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            EnforcePolicy::Once => serializer.serialize_str("once"),
+            EnforcePolicy::Always => serializer.serialize_str("always"),
+            EnforcePolicy::Interval(interval) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("interval", &format_duration(*interval))?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for EnforcePolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EnforcePolicyVisitor;
+
+        impl<'de> Visitor<'de> for EnforcePolicyVisitor {
+            type Value = EnforcePolicy;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("`once`, `always` or `interval: <duration>`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                match value {
+                    "once" => Ok(EnforcePolicy::Once),
+                    "always" => Ok(EnforcePolicy::Always),
+                    _ => Err(E::unknown_variant(value, &["once", "always", "interval"])),
+                }
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let key: String = map
+                    .next_key()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                if key != "interval" {
+                    return Err(de::Error::unknown_field(&key, &["interval"]));
+                }
+                let value: String = map.next_value()?;
+                let interval = parse_duration(&value).map_err(de::Error::custom)?;
+                if interval.is_zero() {
+                    return Err(de::Error::custom("enforce interval must not be zero"));
+                }
+                Ok(EnforcePolicy::Interval(interval))
+            }
+        }
+
+        deserializer.deserialize_any(EnforcePolicyVisitor)
+    }
+}
+
+impl Serialize for OwnerMatcher {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (&self.user, &self.group, self.uid) {
+            (Some(user), None, UidKind::Real) => user.serialize(serializer),
+            _ => {
+                let mut map = serializer.serialize_map(None)?;
+                if let Some(user) = &self.user {
+                    map.serialize_entry("user", user)?;
+                }
+                if let Some(group) = &self.group {
+                    map.serialize_entry("group", group)?;
+                }
+                map.serialize_entry("uid", &self.uid)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OwnerMatcher {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// The map form of an `OwnerMatcher`.
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct OwnerFields {
+            user: Option<UserSpec>,
+            group: Option<GroupSpec>,
+            #[serde(default)]
+            uid: UidKind,
+        }
+
+        struct OwnerMatcherVisitor;
+
+        impl<'de> Visitor<'de> for OwnerMatcherVisitor {
+            type Value = OwnerMatcher;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(
+                    "a user name, UID or UID range, or a map with `user`, `group` and `uid`",
+                )
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                let user = UserSpecVisitor.visit_u64(value)?;
+                Ok(OwnerMatcher {
+                    user: Some(user),
+                    ..Default::default()
+                })
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                let user = UserSpecVisitor.visit_str(value)?;
+                Ok(OwnerMatcher {
+                    user: Some(user),
+                    ..Default::default()
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let fields = OwnerFields::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                if fields.user.is_none() && fields.group.is_none() {
+                    return Err(de::Error::custom("owner requires a user or a group"));
+                }
+                Ok(OwnerMatcher {
+                    user: fields.user,
+                    group: fields.group,
+                    uid: fields.uid,
+                    ..Default::default()
+                })
+            }
+        }
+
+        deserializer.deserialize_any(OwnerMatcherVisitor)
+    }
+}
+
+/// Reads a `UserSpec` from a UID or from a string.
+struct UserSpecVisitor;
+
+impl<'de> Visitor<'de> for UserSpecVisitor {
+    type Value = UserSpec;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a user name, UID or UID range such as `1000-1999`")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        u32::try_from(value)
+            .map(UserSpec::Uid)
+            .map_err(|_| E::custom(format!("UID {} is out of range", value)))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        UserSpec::parse(value).map_err(E::custom)
+    }
+}
+
+impl Serialize for UserSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            UserSpec::Uid(uid) => serializer.serialize_u32(*uid),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for UserSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UserSpecVisitor)
+    }
+}
+
+impl Serialize for GroupSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            GroupSpec::Name(name) => serializer.serialize_str(name),
+            GroupSpec::Gid(gid) => serializer.serialize_u32(*gid),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GroupSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct GroupSpecVisitor;
+
+        impl<'de> Visitor<'de> for GroupSpecVisitor {
+            type Value = GroupSpec;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a group name or GID")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                u32::try_from(value)
+                    .map(GroupSpec::Gid)
+                    .map_err(|_| E::custom(format!("GID {} is out of range", value)))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(match value.parse() {
+                    Ok(gid) => GroupSpec::Gid(gid),
+                    Err(_) => GroupSpec::Name(value.to_string()),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(GroupSpecVisitor)
+    }
+}
+
+/// Returns the default I/O priority level, which is also the kernel's default.
+fn default_ionice_level() -> u8 {
+    4
+}
+
+/// Deserializes an I/O priority level and rejects values outside of 0..=7.
+fn deserialize_ionice_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let level = u8::deserialize(deserializer)?;
+    if level > 7 {
+        return Err(de::Error::custom(format!(
+            "ionice level must be between 0 and 7, got {}",
+            level
+        )));
+    }
+    Ok(level)
+}
+
+/// Deserializes an OOM score adjustment and rejects values outside of -1000..=1000.
+fn deserialize_oom_score_adj<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i32>, D::Error> {
+    let value = Option::<i32>::deserialize(deserializer)?;
+    if let Some(value) = value {
+        if !(-1000..=1000).contains(&value) {
+            return Err(de::Error::custom(format!(
+                "oom_score_adj must be between -1000 and 1000, got {}",
+                value
+            )));
+        }
+    }
+    Ok(value)
+}
+
+/// Parses a human readable duration such as `500ms`, `30s`, `5m` or `1h`.
+/// A plain number is interpreted as seconds.
+///
+/// # Arguments
+///
+/// * `value` - The duration string to parse.
+///
+/// # Returns
+///
+/// * `Ok(Duration)` containing the parsed duration.
+/// * `Err(anyhow::Error)` if the number or the unit is invalid, or the duration is too long.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration: '{}'", value))?;
+
+    let seconds_per_unit = match unit.trim() {
+        "ms" => return Ok(Duration::from_millis(number)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        other => bail!("Invalid duration unit '{}' in '{}'", other, value),
+    };
+    let seconds = number
+        .checked_mul(seconds_per_unit)
+        .with_context(|| format!("Duration '{}' is too long", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Formats a duration the way `parse_duration` reads it, using the largest exact unit.
+///
+/// # Arguments
+///
+/// * `duration` - The duration to format.
+///
+/// # Returns
+///
+/// * A `String` such as `30s` or `1500ms`.
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    match millis {
+        m if m % 3_600_000 == 0 && m > 0 => format!("{}h", m / 3_600_000),
+        m if m % 60_000 == 0 && m > 0 => format!("{}m", m / 60_000),
+        m if m % 1000 == 0 => format!("{}s", m / 1000),
+        m => format!("{}ms", m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that `parse_duration` understands all supported units and bare seconds.
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    /// Tests that durations too long to represent are rejected instead of overflowing.
+    #[test]
+    fn test_parse_duration_overflow() {
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+        assert!(parse_duration(&format!("{}m", u64::MAX / 59)).is_err());
+        assert_eq!(
+            parse_duration(&format!("{}s", u64::MAX)).unwrap(),
+            Duration::from_secs(u64::MAX)
+        );
+        assert!(
+            serde_yaml::from_str::<EnforcePolicy>(&format!("interval: {}h", u64::MAX)).is_err()
+        );
+    }
+
+    /// Tests that all forms of the `enforce` policy are read from YAML, that it defaults
+    /// to `once`, and that it is written back in the same form.
+    #[test]
+    fn test_enforce_policy_yaml() {
+        let yaml = r#"
+process:
+- name: a
+  bin: a
+  nice: 1
+  matcher: { type: simple }
+- name: b
+  bin: b
+  nice: 1
+  enforce: always
+  matcher: { type: simple }
+- name: c
+  bin: c
+  nice: 1
+  enforce:
+    interval: 30s
+  matcher: { type: simple }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.process[0].enforce, EnforcePolicy::Once);
+        assert_eq!(config.process[1].enforce, EnforcePolicy::Always);
+        assert_eq!(
+            config.process[2].enforce,
+            EnforcePolicy::Interval(Duration::from_secs(30))
+        );
+
+        let written = serde_yaml::to_string(&config.process[2].enforce).unwrap();
+        assert_eq!(written.trim(), "interval: 30s");
+    }
+
+    /// Tests that the `ionice` block is read with its default level and that levels outside
+    /// of 0..=7 are rejected.
+    #[test]
+    fn test_ionice_yaml() {
+        let ionice: IoniceConfig = serde_yaml::from_str("class: best-effort").unwrap();
+        assert_eq!(ionice.class, IoClass::BestEffort);
+        assert_eq!(ionice.level, 4);
+
+        let ionice: IoniceConfig = serde_yaml::from_str("{ class: idle, level: 7 }").unwrap();
+        assert_eq!(ionice.normalized().to_string(), "idle");
+
+        assert!(serde_yaml::from_str::<IoniceConfig>("{ class: realtime, level: 8 }").is_err());
+        assert!(serde_yaml::from_str::<IoniceConfig>("class: bogus").is_err());
+    }
+
+    /// Tests that the real-time priority is required for real-time policies and rejected
+    /// for all others.
+    #[test]
+    fn test_sched_validate() {
+        let sched: SchedConfig = serde_yaml::from_str("{ policy: fifo, rt_priority: 50 }").unwrap();
+        assert!(sched.validate().is_ok());
+        assert_eq!(sched.to_string(), "fifo/50");
+
+        let sched: SchedConfig = serde_yaml::from_str("policy: rr").unwrap();
+        assert!(sched.validate().is_err());
+
+        let sched: SchedConfig = serde_yaml::from_str("{ policy: idle, rt_priority: 5 }").unwrap();
+        assert!(sched.validate().is_err());
+
+        let sched: SchedConfig =
+            serde_yaml::from_str("{ policy: normal, reset_on_fork: true }").unwrap();
+        assert!(sched.validate().is_ok());
+        assert_eq!(sched.to_string(), "other (reset-on-fork)");
+    }
+
+    /// Tests that `oom_score_adj` is optional and limited to -1000..=1000.
+    #[test]
+    fn test_oom_score_adj_range() {
+        let rule = "{ name: a, bin: a, nice: 0, matcher: { type: simple }";
+        let parse =
+            |extra: &str| serde_yaml::from_str::<ProcessConfig>(&format!("{}{} }}", rule, extra));
+
+        assert_eq!(parse("").unwrap().oom_score_adj, None);
+        assert_eq!(
+            parse(", oom_score_adj: -1000").unwrap().oom_score_adj,
+            Some(-1000)
+        );
+        assert_eq!(
+            parse(", oom_score_adj: 1000").unwrap().oom_score_adj,
+            Some(1000)
+        );
+        assert!(parse(", oom_score_adj: 1001").is_err());
+        assert!(parse(", oom_score_adj: -1001").is_err());
+    }
+
+    /// Tests that cgroup names and resource control values are validated.
+    #[test]
+    fn test_cgroup_validate() {
+        let parse = |yaml: &str| serde_yaml::from_str::<CgroupConfig>(yaml).unwrap();
+
+        let cgroup = parse("{ name: batch, cpu_weight: 50, cpu_max: max, memory_high: 512M }");
+        assert!(cgroup.validate().is_ok());
+        assert_eq!(cgroup.controllers(), vec!["cpu", "memory"]);
+
+        assert!(parse("{ name: batch, cpu_max: '50000 100000' }")
+            .validate()
+            .is_ok());
+        assert!(parse("{ name: ../x }").validate().is_err());
+        assert!(parse("{ name: batch, io_weight: 0 }").validate().is_err());
+        assert!(parse("{ name: batch, cpu_max: 'half' }")
+            .validate()
+            .is_err());
+        assert!(parse("{ name: batch, memory_high: lots }")
+            .validate()
+            .is_err());
+        assert!(parse("{ name: batch, memory_high: 2GG }")
+            .validate()
+            .is_err());
+        assert!(!cgroup.move_services);
+        assert!(parse("{ name: batch, move_services: true }").move_services);
+    }
+
+    /// Tests that regex patterns are compiled when the configuration is prepared, and that an
+    /// invalid pattern is reported as an error naming the rule.
+    #[test]
+    fn test_prepare_compiles_regex() {
+        let yaml = "process: [{ name: good, bin: a, nice: 0, matcher: { type: regex, match_string: '^a+$' } }]";
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        config.prepare().unwrap();
+        assert!(config.process[0].matcher.compiled.is_some());
+
+        let yaml = "process: [{ name: broken, bin: a, nice: 0, matcher: { type: regex, match_string: '(a' } }]";
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        let err = format!("{:#}", config.prepare().unwrap_err());
+        assert!(err.contains("'broken'"), "{}", err);
+
+        let yaml = "process: [{ name: empty, bin: a, nice: 0, matcher: { type: regex } }]";
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.prepare().is_err());
+    }
+
+    /// Tests that thread rules are compiled by `prepare`, that the first matching thread rule
+    /// wins, and that thread rules without a setting are rejected.
+    #[test]
+    fn test_thread_rules() {
+        let yaml = r#"
+process:
+- name: browser
+  bin: firefox
+  nice: 0
+  matcher: { type: comm }
+  threads:
+  - { comm: "Compositor", nice: -5 }
+  - { comm: "*Thread*", sched: { policy: batch }, affinity: "0-1" }
+"#;
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        config.prepare().unwrap();
+        let rule = &config.process[0];
+        assert_eq!(
+            rule.thread_rule("Compositor").and_then(|t| t.nice),
+            Some(-5)
+        );
+        assert!(rule.thread_rule("AudioThread").unwrap().sched.is_some());
+        assert!(rule.thread_rule("Renderer").is_none());
+
+        let yaml = "process: [{ name: idle, bin: a, nice: 0, matcher: { type: comm }, threads: [{ comm: worker }] }]";
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        let err = format!("{:#}", config.prepare().unwrap_err());
+        assert!(err.contains("'idle'"), "{}", err);
+    }
+
+    /// Tests that the built-in `protect` list is compiled, that a configured list replaces
+    /// only its own default, and that relative executable paths are rejected.
+    #[test]
+    fn test_protect_config() {
+        let protect = ProtectConfig::default();
+        assert_eq!(protect.compiled_units.len(), protect.units.len());
+        assert!(protect.compiled_kernel_threads[1].is_match("migration/3"));
+
+        let mut config: Config =
+            serde_yaml::from_str("process: []\nprotect: { units: ['app-*.scope'] }").unwrap();
+        config.prepare().unwrap();
+        assert_eq!(config.protect.pids, vec![1]);
+        assert_eq!(config.protect.compiled_units.len(), 1);
+
+        let mut config: Config =
+            serde_yaml::from_str("process: []\nprotect: { exe: [bin/sh] }").unwrap();
+        assert!(config.prepare().is_err());
+    }
+
+    /// Tests that globs are translated into anchored regular expressions, with regex
+    /// metacharacters escaped and unterminated classes taken literally.
+    #[test]
+    fn test_glob_to_regex() {
+        assert_eq!(glob_to_regex("*/bin/cc?"), r"^.*/bin/cc.$");
+        assert_eq!(glob_to_regex("a.b[!0-9]"), r"^a\.b[^0-9]$");
+        assert_eq!(glob_to_regex("[]x]"), r"^[\]x]$");
+        assert_eq!(glob_to_regex("a[b"), r"^a\[b$");
+    }
+
+    /// Tests that comm matchers longer than the kernel's limit are rejected, since they
+    /// could never match.
+    #[test]
+    fn test_compile_rejects_long_comm() {
+        let mut matcher = MatcherConfig {
+            r#type: String::from("comm"),
+            match_string: Some(String::from("a-very-long-process-name")),
+            ..Default::default()
+        };
+        assert!(matcher.compile().is_err());
+        matcher.match_string = Some(String::from("kworker"));
+        assert!(matcher.compile().is_ok());
+    }
+
+    /// Tests that a comm matcher without a match_string is checked against the bin of its
+    /// rule, at any depth, since a path or a long bin could never match.
+    #[test]
+    fn test_prepare_checks_comm_bin_fallback() {
+        let rule = |bin: &str, matcher: &str| {
+            let yaml = format!(
+                "process: [{{ name: r, bin: '{}', nice: 0, matcher: {} }}]",
+                bin, matcher
+            );
+            let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+            config.prepare()
+        };
+        assert!(rule("pipewire", "{ type: comm }").is_ok());
+        assert!(rule("/usr/bin/pipewire", "{ type: comm }").is_err());
+        assert!(rule("a-very-long-process-name", "{ type: comm }").is_err());
+        assert!(rule(
+            "/usr/bin/pipewire",
+            "{ all: [{ type: simple }, { type: comm }] }"
+        )
+        .is_err());
+        assert!(rule("/usr/bin/pipewire", "{ type: simple, not: { type: comm } }").is_err());
+        assert!(rule(
+            "/usr/bin/pipewire",
+            "{ type: comm, match_string: pipewire }"
+        )
+        .is_ok());
+    }
+
+    /// Tests that an empty `all` or `any` is rejected with an error naming the rule, since
+    /// an empty `all` would match every process.
+    #[test]
+    fn test_prepare_rejects_empty_combinators() {
+        for matcher in [
+            "{ all: [] }",
+            "{ any: [] }",
+            "{ type: simple, not: { all: [] } }",
+        ] {
+            let yaml = format!(
+                "process: [{{ name: empty, bin: a, nice: 0, matcher: {} }}]",
+                matcher
+            );
+            let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+            let err = format!("{:#}", config.prepare().unwrap_err());
+            assert!(err.contains("'empty'"), "{}", err);
+        }
+    }
+
+    /// Tests that user rules cannot opt out of the protect list or adjust kernel threads,
+    /// neither as new rules nor by overriding a global rule.
+    #[test]
+    fn test_merge_ignores_global_only_options_of_users() {
+        let global: Config = serde_yaml::from_str(
+            "process: [{ name: journald, bin: systemd-journald, nice: 0, matcher: { type: comm } }]",
+        )
+        .unwrap();
+        let mut local: Config = serde_yaml::from_str(
+            r#"
+process:
+- { name: journald, bin: systemd-journald, nice: 19, allow_protected: true, matcher: { type: comm } }
+- { name: kthreads, bin: x, nice: 19, kernel_thread: true, matcher: { type: comm_glob, match_string: "*" } }
+- { name: own, bin: make, nice: 10, matcher: { type: comm } }
+"#,
+        )
+        .unwrap();
+        for process in &mut local.process {
+            process.source = RuleSource::User(String::from("mallory"));
+        }
+
+        let merged = Config::merge(global, local);
+        let names: Vec<&str> = merged.process.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["journald", "own"]);
+        assert!(!merged.process[0].allow_protected);
+        assert_eq!(merged.process[0].nice, 0);
+        assert_eq!(merged.process[0].source, RuleSource::Global);
+        assert!(merged.process.iter().all(|p| !p.kernel_thread));
+    }
+
+    /// Tests that a misspelled matcher type is rejected with the list of valid types,
+    /// instead of silently never matching.
+    #[test]
+    fn test_compile_rejects_unknown_type() {
+        let yaml =
+            "process: [{ name: typo, bin: a, nice: 0, matcher: { type: rgex, match_string: a } }]";
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        let err = format!("{:#}", config.prepare().unwrap_err());
+        assert!(err.contains("'typo'"), "{}", err);
+        assert!(err.contains("'rgex'") && err.contains("regex"), "{}", err);
+
+        for r#type in MATCHER_TYPES {
+            let mut matcher = MatcherConfig {
+                r#type: String::from(r#type),
+                match_string: Some(String::from("/usr/bin/a")),
+                argv0: Some(String::from("a")),
+                ..Default::default()
+            };
+            assert!(matcher.compile().is_ok(), "{}", r#type);
+        }
+    }
+
+    /// Tests that argv options are checked for consistency when they are compiled.
+    #[test]
+    fn test_compile_argv_options() {
+        let mut matcher = MatcherConfig {
+            r#type: String::from("argv"),
+            ..Default::default()
+        };
+        assert!(matcher.compile().is_err());
+
+        matcher.arg_index = Some(1);
+        matcher.argv0 = Some(String::from("electron"));
+        assert!(matcher.compile().is_err());
+
+        matcher.arg_regex = Some(String::from("^--type=(renderer|gpu-process)$"));
+        assert!(matcher.compile().is_ok());
+        assert!(matcher.compiled_arg_regex.is_some());
+
+        matcher.arg_index = Some(0);
+        assert!(matcher.compile().is_err());
+    }
+
+    /// Tests that owners are read as names, UIDs, UID ranges or maps, that hyphenated
+    /// names are not taken for ranges, and that the short form is written back.
+    #[test]
+    fn test_owner_matcher_yaml() {
+        let owner = |yaml: &str| serde_yaml::from_str::<OwnerMatcher>(yaml);
+
+        assert_eq!(owner("www-data").unwrap(), OwnerMatcher::user("www-data"));
+        assert_eq!(owner("1000").unwrap().user, Some(UserSpec::Uid(1000)));
+        assert_eq!(owner("'1000'").unwrap().user, Some(UserSpec::Uid(1000)));
+        assert_eq!(
+            owner("'100000-165535'").unwrap().user,
+            Some(UserSpec::Range(100000, 165535))
+        );
+        let map = owner("{ group: video, uid: effective }").unwrap();
+        assert_eq!(map.group, Some(GroupSpec::Name(String::from("video"))));
+        assert_eq!(map.uid, UidKind::Effective);
+
+        assert!(owner("'2000-1000'").is_err());
+        assert!(owner("{ uid: effective }").is_err());
+        assert!(owner("{ user: 0, gid: 0 }").is_err());
+        assert!(owner("-1").is_err());
+
+        assert_eq!(
+            serde_yaml::to_string(&owner("'1000-1999'").unwrap()).unwrap(),
+            "1000-1999\n"
+        );
+        let yaml = serde_yaml::to_string(&map).unwrap();
+        assert_eq!(owner(&yaml).unwrap(), map);
+    }
+
+    /// Tests that unknown policies and zero intervals are rejected.
+    #[test]
+    fn test_enforce_policy_rejects_invalid_values() {
+        assert!(serde_yaml::from_str::<EnforcePolicy>("sometimes").is_err());
+        assert!(serde_yaml::from_str::<EnforcePolicy>("interval: 0s").is_err());
+        assert!(serde_yaml::from_str::<EnforcePolicy>("period: 5s").is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::{Context, Result};
+use nix::libc;
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+
+use crate::{debug, trace};
+
+/// Connector index and value of the kernel process events connector (`linux/connector.h`).
+const CN_IDX_PROC: u32 = 0x1;
+const CN_VAL_PROC: u32 = 0x1;
+
+/// Multicast control operation that subscribes to process events (`linux/cn_proc.h`).
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+/// Process event types we care about (`enum what` in `linux/cn_proc.h`).
+const PROC_EVENT_FORK: u32 = 0x0000_0001;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_UID: u32 = 0x0000_0004;
+const PROC_EVENT_COMM: u32 = 0x0000_0200;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+/// Sizes of the fixed headers that precede the event payload.
+const NLMSG_HDRLEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+const PROC_EVENT_HDRLEN: usize = 16;
+
+/// A process event delivered by the kernel proc connector.
+///
+/// Only the fields reniced needs are decoded. `pid` is the thread ID and `tgid`
+/// the process ID as seen from userspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcEvent {
+    /// A new task was created by `fork`/`clone`.
+    Fork {
+        parent_tgid: i32,
+        child_pid: i32,
+        child_tgid: i32,
+    },
+    /// A task replaced its program image with `exec`.
+    Exec { pid: i32, tgid: i32 },
+    /// A task changed its real or effective UID.
+    Uid {
+        pid: i32,
+        tgid: i32,
+        ruid: u32,
+        euid: u32,
+    },
+    /// A task changed its `comm` name.
+    Comm { pid: i32, tgid: i32, comm: String },
+    /// A task exited.
+    Exit { pid: i32, tgid: i32 },
+}
+
+/// A subscription to the kernel proc connector over a `NETLINK_CONNECTOR` socket.
+///
+/// Opening the connector requires `CAP_NET_ADMIN`. Callers are expected to fall back
+/// to polling `/proc` when [`ProcConnector::open`] fails.
+pub struct ProcConnector {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl ProcConnector {
+    /// Opens a netlink connector socket, joins the proc events group and asks the kernel
+    /// to start multicasting process events.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ProcConnector)` if the subscription was set up successfully.
+    /// * `Err(anyhow::Error)` if the socket could not be created, bound or subscribed.
+    pub fn open() -> Result<Self> {
+        debug!("Opening netlink proc connector");
+        let raw = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::NETLINK_CONNECTOR,
+            )
+        };
+        if raw < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to create netlink socket");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = CN_IDX_PROC;
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error())
+                .context("Failed to bind to the proc connector group");
+        }
+
+        let message = listen_message();
+        let sent = unsafe {
+            libc::send(
+                fd.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error())
+                .context("Failed to subscribe to process events");
+        }
+
+        let fd = AsyncFd::new(fd).context("Failed to register netlink socket")?;
+        debug!("Subscribed to process events");
+        Ok(ProcConnector { fd })
+    }
+
+    /// Waits for the next batch of process events.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<ProcEvent>)` with the events decoded from one datagram (possibly empty).
+    /// * `Err(io::Error)` if receiving failed. `ENOBUFS` means the kernel dropped events
+    ///   because we did not keep up, and the caller should rescan `/proc`.
+    pub async fn recv(&self) -> io::Result<Vec<ProcEvent>> {
+        let mut buf = [0u8; 8192];
+
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let result = guard.try_io(|fd| {
+                let len = unsafe {
+                    libc::recv(
+                        fd.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                        0,
+                    )
+                };
+                if len < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(len as usize)
+                }
+            });
+
+            match result {
+                Ok(Ok(len)) => return Ok(parse_messages(&buf[..len])),
+                Ok(Err(err)) => return Err(err),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Builds the netlink message that subscribes this socket to process events.
+///
+/// # Returns
+///
+/// * The raw bytes of an `nlmsghdr` + `cn_msg` + `PROC_CN_MCAST_LISTEN` operation.
+fn listen_message() -> Vec<u8> {
+    let payload_len = size_of::<u32>();
+    let total_len = NLMSG_HDRLEN + CN_MSG_LEN + payload_len;
+    let mut message = Vec::with_capacity(total_len);
+
+    // struct nlmsghdr
+    message.extend_from_slice(&(total_len as u32).to_ne_bytes());
+    message.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+    message.extend_from_slice(&0u16.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(&std::process::id().to_ne_bytes());
+
+    // struct cn_msg
+    message.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+    message.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(&(payload_len as u16).to_ne_bytes());
+    message.extend_from_slice(&0u16.to_ne_bytes());
+
+    // enum proc_cn_mcast_op
+    message.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+
+    message
+}
+
+/// Decodes all netlink messages in a received datagram into process events.
+///
+/// # Arguments
+///
+/// * `buf` - The bytes received from the netlink socket.
+///
+/// # Returns
+///
+/// * A `Vec<ProcEvent>` with every event that could be decoded. Unknown or truncated
+///   messages are skipped.
+fn parse_messages(buf: &[u8]) -> Vec<ProcEvent> {
+    let mut events = Vec::new();
+    let mut offset = 0;
+
+    while offset + NLMSG_HDRLEN <= buf.len() {
+        let msg_len = read_u32(buf, offset).unwrap_or(0) as usize;
+        if msg_len < NLMSG_HDRLEN || offset + msg_len > buf.len() {
+            trace!("Skipping truncated netlink message");
+            break;
+        }
+
+        if let Some(event) = parse_message(&buf[offset..offset + msg_len]) {
+            events.push(event);
+        }
+
+        // Netlink messages are aligned to 4 bytes.
+        offset += (msg_len + 3) & !3;
+    }
+
+    events
+}
+
+/// Decodes a single netlink message into a process event.
+///
+/// # Arguments
+///
+/// * `msg` - The bytes of one netlink message, including its header.
+///
+/// # Returns
+///
+/// * `Some(ProcEvent)` if the message carries a process event we handle.
+/// * `None` for other connector messages and event types.
+fn parse_message(msg: &[u8]) -> Option<ProcEvent> {
+    let cn = NLMSG_HDRLEN;
+    if read_u32(msg, cn)? != CN_IDX_PROC || read_u32(msg, cn + 4)? != CN_VAL_PROC {
+        return None;
+    }
+
+    let ev = cn + CN_MSG_LEN;
+    let what = read_u32(msg, ev)?;
+    let data = ev + PROC_EVENT_HDRLEN;
+
+    match what {
+        PROC_EVENT_FORK => Some(ProcEvent::Fork {
+            parent_tgid: read_i32(msg, data + 4)?,
+            child_pid: read_i32(msg, data + 8)?,
+            child_tgid: read_i32(msg, data + 12)?,
+        }),
+        PROC_EVENT_EXEC => Some(ProcEvent::Exec {
+            pid: read_i32(msg, data)?,
+            tgid: read_i32(msg, data + 4)?,
+        }),
+        PROC_EVENT_UID => Some(ProcEvent::Uid {
+            pid: read_i32(msg, data)?,
+            tgid: read_i32(msg, data + 4)?,
+            ruid: read_u32(msg, data + 8)?,
+            euid: read_u32(msg, data + 12)?,
+        }),
+        PROC_EVENT_COMM => {
+            let raw = msg.get(data + 8..data + 24)?;
+            let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+            Some(ProcEvent::Comm {
+                pid: read_i32(msg, data)?,
+                tgid: read_i32(msg, data + 4)?,
+                comm: String::from_utf8_lossy(&raw[..end]).into_owned(),
+            })
+        }
+        PROC_EVENT_EXIT => Some(ProcEvent::Exit {
+            pid: read_i32(msg, data)?,
+            tgid: read_i32(msg, data + 4)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads a native-endian `u32` at `offset`, or `None` if the buffer is too short.
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a native-endian `i32` at `offset`, or `None` if the buffer is too short.
+fn read_i32(buf: &[u8], offset: usize) -> Option<i32> {
+    read_u32(buf, offset).map(|value| value as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper function to build a netlink message carrying a process event with the given
+    /// `what` value and payload, laid out the way the kernel sends it.
+    fn build_event(what: u32, payload: &[u8]) -> Vec<u8> {
+        let total_len = NLMSG_HDRLEN + CN_MSG_LEN + PROC_EVENT_HDRLEN + payload.len();
+        let mut msg = Vec::with_capacity(total_len);
+        msg.extend_from_slice(&(total_len as u32).to_ne_bytes());
+        msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 10]);
+        msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 8]);
+        msg.extend_from_slice(&((PROC_EVENT_HDRLEN + payload.len()) as u16).to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 2]);
+        msg.extend_from_slice(&what.to_ne_bytes());
+        msg.extend_from_slice(&[0u8; 12]);
+        msg.extend_from_slice(payload);
+        msg
+    }
+
+    /// Helper function to encode a list of `u32` fields as an event payload.
+    fn payload(fields: &[u32]) -> Vec<u8> {
+        fields.iter().flat_map(|f| f.to_ne_bytes()).collect()
+    }
+
+    /// Tests that the subscription message has the layout the kernel expects.
+    #[test]
+    fn test_listen_message_layout() {
+        let msg = listen_message();
+        assert_eq!(msg.len(), NLMSG_HDRLEN + CN_MSG_LEN + 4);
+        assert_eq!(read_u32(&msg, 0), Some(msg.len() as u32));
+        assert_eq!(read_u32(&msg, NLMSG_HDRLEN), Some(CN_IDX_PROC));
+        assert_eq!(read_u32(&msg, NLMSG_HDRLEN + 4), Some(CN_VAL_PROC));
+        assert_eq!(
+            read_u32(&msg, NLMSG_HDRLEN + CN_MSG_LEN),
+            Some(PROC_CN_MCAST_LISTEN)
+        );
+    }
+
+    /// Tests that fork, exec and exit events packed into one datagram are all decoded.
+    #[test]
+    fn test_parse_messages_decodes_multiple_events() {
+        let mut buf = build_event(PROC_EVENT_FORK, &payload(&[10, 10, 11, 11]));
+        buf.extend(build_event(PROC_EVENT_EXEC, &payload(&[11, 11])));
+        buf.extend(build_event(
+            PROC_EVENT_EXIT,
+            &payload(&[11, 11, 0, 17, 10, 10]),
+        ));
+
+        let events = parse_messages(&buf);
+        assert_eq!(
+            events,
+            vec![
+                ProcEvent::Fork {
+                    parent_tgid: 10,
+                    child_pid: 11,
+                    child_tgid: 11
+                },
+                ProcEvent::Exec { pid: 11, tgid: 11 },
+                ProcEvent::Exit { pid: 11, tgid: 11 },
+            ]
+        );
+    }
+
+    /// Tests that the NUL-padded `comm` field of a comm event is decoded as a string.
+    #[test]
+    fn test_parse_messages_decodes_comm() {
+        let mut data = payload(&[42, 40]);
+        let mut comm = [0u8; 16];
+        comm[..6].copy_from_slice(b"worker");
+        data.extend_from_slice(&comm);
+
+        let events = parse_messages(&build_event(PROC_EVENT_COMM, &data));
+        assert_eq!(
+            events,
+            vec![ProcEvent::Comm {
+                pid: 42,
+                tgid: 40,
+                comm: String::from("worker")
+            }]
+        );
+    }
+
+    /// Tests that truncated messages and unknown event types are skipped instead of
+    /// producing bogus events.
+    #[test]
+    fn test_parse_messages_skips_unknown_and_truncated() {
+        let unknown = build_event(0x40, &payload(&[1, 1, 0, 0]));
+        assert!(parse_messages(&unknown).is_empty());
+
+        let fork = build_event(PROC_EVENT_FORK, &payload(&[10, 10, 11, 11]));
+        assert!(parse_messages(&fork[..fork.len() - 4]).is_empty());
+    }
+}
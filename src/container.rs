@@ -0,0 +1,320 @@
+use nix::unistd::{Uid, User};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The container runtimes whose containers can be recognized from their cgroup path.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Runtime {
+    /// Podman, rootful or rootless (`libpod-<id>.scope`).
+    Podman,
+    /// Docker (`docker-<id>.scope` or `/docker/<id>`).
+    Docker,
+    /// containerd through its CRI plugin (`cri-containerd-<id>.scope`).
+    Containerd,
+    /// CRI-O (`crio-<id>.scope`).
+    #[serde(rename = "cri-o")]
+    CriO,
+    /// The process runs in a PID namespace of its own, but its runtime is not known.
+    Unknown,
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Runtime::Podman => "podman",
+            Runtime::Docker => "docker",
+            Runtime::Containerd => "containerd",
+            Runtime::CriO => "cri-o",
+            Runtime::Unknown => "unknown",
+        })
+    }
+}
+
+/// The container a process runs in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerInfo {
+    /// The runtime that started the container.
+    pub runtime: Runtime,
+    /// The full container ID, empty if the runtime is unknown.
+    pub id: String,
+    /// The UID of the user running a rootless container, taken from its `user@<uid>.service`.
+    pub user: Option<u32>,
+}
+
+/// The scope name prefixes the runtimes use for container cgroups, e.g. `libpod-<id>.scope`.
+const SCOPE_PREFIXES: [(&str, Runtime); 4] = [
+    ("libpod-", Runtime::Podman),
+    ("docker-", Runtime::Docker),
+    ("cri-containerd-", Runtime::Containerd),
+    ("crio-", Runtime::CriO),
+];
+
+/// Determines the container of a process from its cgroup path, falling back to comparing
+/// its PID namespace with the one reniced runs in.
+///
+/// # Arguments
+///
+/// * `pid` - The PID of the process.
+/// * `cgroup` - The cgroup v2 path of the process, if known.
+///
+/// # Returns
+///
+/// * `Some(ContainerInfo)` if the process runs in a container.
+/// * `None` if it runs on the host.
+pub fn get_container_for_pid(pid: i32, cgroup: Option<&str>) -> Option<ContainerInfo> {
+    if let Some(container) = cgroup.and_then(parse_container_from_cgroup) {
+        return Some(container);
+    }
+
+    let namespace = fs::read_link(format!("/proc/{}/ns/pid", pid)).ok()?;
+    let own_namespace = fs::read_link("/proc/self/ns/pid").ok()?;
+    (namespace != own_namespace).then(|| ContainerInfo {
+        runtime: Runtime::Unknown,
+        id: String::new(),
+        user: None,
+    })
+}
+
+/// Recognizes the container scopes of podman, docker, containerd and CRI-O in a cgroup
+/// path, with both the systemd and the cgroupfs cgroup drivers.
+///
+/// # Arguments
+///
+/// * `path` - The cgroup v2 path of a process.
+///
+/// # Returns
+///
+/// * `Some(ContainerInfo)` for the innermost container in the path.
+/// * `None` if the path does not belong to a container. `conmon` processes are not
+///   inside their container and are not recognized.
+pub fn parse_container_from_cgroup(path: &str) -> Option<ContainerInfo> {
+    let components: Vec<&str> = path.split('/').collect();
+    let user = components.iter().find_map(|component| {
+        component
+            .strip_prefix("user@")?
+            .strip_suffix(".service")?
+            .parse()
+            .ok()
+    });
+
+    for (index, component) in components.iter().enumerate().rev() {
+        let name = component.strip_suffix(".scope").unwrap_or(component);
+        let scoped = SCOPE_PREFIXES.iter().find_map(|(prefix, runtime)| {
+            let id = name.strip_prefix(prefix)?;
+            is_container_id(id).then_some((*runtime, id))
+        });
+        let cgroupfs_docker =
+            (index > 0 && components[index - 1] == "docker" && is_container_id(component))
+                .then_some((Runtime::Docker, *component));
+
+        if let Some((runtime, id)) = scoped.or(cgroupfs_docker) {
+            return Some(ContainerInfo {
+                runtime,
+                id: id.to_string(),
+                user,
+            });
+        }
+    }
+
+    None
+}
+
+/// Checks whether a string is a full container ID (64 hexadecimal digits).
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The parts of docker's `config.v2.json` needed to find a container name.
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+/// The parts of an entry of the containers/storage `containers.json` (podman, CRI-O).
+#[derive(Deserialize)]
+struct StorageContainer {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+}
+
+/// Looks up the name of a container in the state files its runtime keeps on disk.
+/// Rootless containers are looked up in the home directory of the user running them.
+///
+/// # Arguments
+///
+/// * `container` - The container to look up.
+///
+/// # Returns
+///
+/// * `Some(String)` with the container name.
+/// * `None` if the runtime keeps no readable state files (e.g. containerd) or the
+///   container was not found.
+pub fn lookup_container_name(container: &ContainerInfo) -> Option<String> {
+    let home = container
+        .user
+        .and_then(|uid| User::from_uid(Uid::from_raw(uid)).ok().flatten())
+        .map(|user| user.dir.join(".local/share"));
+
+    match container.runtime {
+        Runtime::Docker => {
+            let roots = [
+                Some(PathBuf::from("/var/lib/docker")),
+                home.map(|home| home.join("docker")),
+            ];
+            roots.iter().flatten().find_map(|root| {
+                let path = root
+                    .join("containers")
+                    .join(&container.id)
+                    .join("config.v2.json");
+                let content = fs::read_to_string(path).ok()?;
+                parse_docker_name(&content)
+            })
+        }
+        Runtime::Podman | Runtime::CriO => {
+            let roots = [
+                Some(PathBuf::from("/var/lib/containers/storage")),
+                home.map(|home| home.join("containers/storage")),
+            ];
+            roots
+                .iter()
+                .flatten()
+                .find_map(|root| storage_container_name(root, &container.id))
+        }
+        Runtime::Containerd | Runtime::Unknown => None,
+    }
+}
+
+/// Finds a container name in the `containers.json` of a containers/storage root.
+///
+/// # Arguments
+///
+/// * `root` - The storage root, e.g. `/var/lib/containers/storage`.
+/// * `id` - The full container ID.
+///
+/// # Returns
+///
+/// * `Some(String)` with the first name of the container.
+/// * `None` if no storage driver lists the container.
+fn storage_container_name(root: &Path, id: &str) -> Option<String> {
+    ["overlay", "btrfs", "vfs"].iter().find_map(|driver| {
+        let path = root
+            .join(format!("{}-containers", driver))
+            .join("containers.json");
+        let content = fs::read_to_string(path).ok()?;
+        parse_storage_name(&content, id)
+    })
+}
+
+/// Extracts the container name from docker's `config.v2.json`.
+///
+/// # Arguments
+///
+/// * `content` - The contents of the file.
+///
+/// # Returns
+///
+/// * `Some(String)` with the name, without docker's leading `/`.
+/// * `None` if the file could not be parsed.
+fn parse_docker_name(content: &str) -> Option<String> {
+    // JSON is valid YAML, so the YAML parser reads the runtime's state files as well.
+    let config: DockerConfig = serde_yaml::from_str(content).ok()?;
+    Some(config.name.trim_start_matches('/').to_string())
+}
+
+/// Extracts the name of a container from a containers/storage `containers.json`.
+///
+/// # Arguments
+///
+/// * `content` - The contents of the file.
+/// * `id` - The full container ID.
+///
+/// # Returns
+///
+/// * `Some(String)` with the first name of the container.
+/// * `None` if the file could not be parsed or does not list the container.
+fn parse_storage_name(content: &str, id: &str) -> Option<String> {
+    let containers: Vec<StorageContainer> = serde_yaml::from_str(content).ok()?;
+    containers
+        .into_iter()
+        .find(|container| container.id == id)?
+        .names
+        .into_iter()
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f2a9c1e5b7d4f60a8c2e4b6d8f0a1c3e5b7d9f1a3c5e7b9d1f3a5c7e9b1d3f5";
+
+    /// Tests that containers of each runtime are recognized with the systemd cgroup driver,
+    /// including rootless podman below a user's service.
+    #[test]
+    fn test_parse_container_from_systemd_scopes() {
+        let podman = format!(
+            "/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{}.scope/container",
+            ID
+        );
+        assert_eq!(
+            parse_container_from_cgroup(&podman),
+            Some(ContainerInfo {
+                runtime: Runtime::Podman,
+                id: ID.to_string(),
+                user: Some(1000)
+            })
+        );
+
+        let docker = format!("/system.slice/docker-{}.scope", ID);
+        let containerd = format!(
+            "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1.slice/cri-containerd-{}.scope",
+            ID
+        );
+        let crio = format!("/kubepods.slice/crio-{}.scope", ID);
+        let runtime = |path: &str| parse_container_from_cgroup(path).map(|c| c.runtime);
+        assert_eq!(runtime(&docker), Some(Runtime::Docker));
+        assert_eq!(runtime(&containerd), Some(Runtime::Containerd));
+        assert_eq!(runtime(&crio), Some(Runtime::CriO));
+    }
+
+    /// Tests that cgroupfs docker paths are recognized, and that conmon and host processes
+    /// are not mistaken for containers.
+    #[test]
+    fn test_parse_container_other_paths() {
+        let docker = format!("/docker/{}", ID);
+        assert_eq!(
+            parse_container_from_cgroup(&docker).map(|c| c.runtime),
+            Some(Runtime::Docker)
+        );
+
+        let conmon = format!("/machine.slice/libpod-conmon-{}.scope", ID);
+        assert_eq!(parse_container_from_cgroup(&conmon), None);
+        assert_eq!(
+            parse_container_from_cgroup("/system.slice/sshd.service"),
+            None
+        );
+        assert_eq!(parse_container_from_cgroup("/docker/not-an-id"), None);
+    }
+
+    /// Tests that container names are read from docker's and containers/storage state files.
+    #[test]
+    fn test_parse_state_files() {
+        let docker = r#"{"ID":"abc","Name":"/ci-runner-1","State":{"Running":true}}"#;
+        assert_eq!(parse_docker_name(docker).as_deref(), Some("ci-runner-1"));
+
+        let storage = format!(
+            r#"[{{"id":"other","names":["web"]}},{{"id":"{}","names":["ci-build","alias"],"image":"x"}}]"#,
+            ID
+        );
+        assert_eq!(
+            parse_storage_name(&storage, ID).as_deref(),
+            Some("ci-build")
+        );
+        assert_eq!(parse_storage_name(&storage, "missing"), None);
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::{bail, Context, Result};
+use nix::libc;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+
+/// The number of CPUs an affinity mask (`cpu_set_t`) can hold. Higher CPU numbers cannot
+/// be used for affinity.
+const MAX_CPUS: usize = libc::CPU_SETSIZE as usize;
+
+/// A set of CPUs written in the kernel's cpulist syntax, e.g. `0-3,8`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuList(BTreeSet<usize>);
+
+impl CpuList {
+    /// Parses a cpulist such as `0-3,8,10-11`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The cpulist string.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CpuList)` containing the listed CPUs.
+    /// * `Err(anyhow::Error)` if a range is malformed, reversed, or the list is empty, or a
+    ///   CPU number does not fit into an affinity mask.
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut cpus = BTreeSet::new();
+
+        for part in value.trim().split(',').map(str::trim) {
+            if part.is_empty() {
+                continue;
+            }
+            let (start, end) = match part.split_once('-') {
+                Some((start, end)) => (start.trim(), end.trim()),
+                None => (part, part),
+            };
+            let start: usize = start
+                .parse()
+                .with_context(|| format!("Invalid CPU '{}' in cpulist '{}'", start, value))?;
+            let end: usize = end
+                .parse()
+                .with_context(|| format!("Invalid CPU '{}' in cpulist '{}'", end, value))?;
+            if start > end {
+                bail!("Invalid CPU range '{}' in cpulist '{}'", part, value);
+            }
+            if end >= MAX_CPUS {
+                bail!(
+                    "CPU {} in cpulist '{}' is out of range, the highest supported CPU is {}",
+                    end,
+                    value,
+                    MAX_CPUS - 1
+                );
+            }
+            cpus.extend(start..=end);
+        }
+
+        if cpus.is_empty() {
+            bail!("Empty cpulist '{}'", value);
+        }
+        Ok(CpuList(cpus))
+    }
+
+    /// Reads the CPUs that are currently online from `/sys/devices/system/cpu/online`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CpuList)` containing the online CPUs.
+    /// * `Err(anyhow::Error)` if the file could not be read or parsed.
+    pub fn online() -> Result<Self> {
+        let path = "/sys/devices/system/cpu/online";
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        Self::parse(&content)
+    }
+
+    /// Creates a `CpuList` from the given CPU numbers.
+    pub fn from_cpus(cpus: impl IntoIterator<Item = usize>) -> Self {
+        CpuList(cpus.into_iter().collect())
+    }
+
+    /// Returns an iterator over the CPU numbers in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Returns the CPUs contained in both lists.
+    pub fn intersection(&self, other: &CpuList) -> CpuList {
+        CpuList(self.0.intersection(&other.0).copied().collect())
+    }
+
+    /// Returns `true` if the list contains no CPUs.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for CpuList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut cpus = self.iter().peekable();
+        let mut first = true;
+
+        while let Some(start) = cpus.next() {
+            let mut end = start;
+            while cpus.peek() == Some(&(end + 1)) {
+                end = cpus.next().unwrap_or(end);
+            }
+
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+
+            if start == end {
+                write!(f, "{}", start)?;
+            } else {
+                write!(f, "{}-{}", start, end)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for CpuList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // A single CPU may be written as a plain YAML number.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(usize),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(cpu) => CpuList::parse(&cpu.to_string()).map_err(de::Error::custom),
+            Raw::Text(text) => CpuList::parse(&text).map_err(de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that single CPUs and ranges are parsed and merged.
+    #[test]
+    fn test_parse_ranges() {
+        let list = CpuList::parse("0-3,8, 10-11").unwrap();
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 8, 10, 11]);
+    }
+
+    /// Tests that malformed and empty cpulists are rejected.
+    #[test]
+    fn test_parse_invalid() {
+        assert!(CpuList::parse("").is_err());
+        assert!(CpuList::parse("3-1").is_err());
+        assert!(CpuList::parse("a").is_err());
+        assert!(CpuList::parse("1-").is_err());
+    }
+
+    /// Tests that CPU numbers beyond what an affinity mask holds are rejected, even as
+    /// part of a huge range, and the highest supported CPU is accepted.
+    #[test]
+    fn test_parse_out_of_range() {
+        let last = MAX_CPUS - 1;
+        assert_eq!(
+            CpuList::parse(&last.to_string()).unwrap().iter().last(),
+            Some(last)
+        );
+        assert!(CpuList::parse(&MAX_CPUS.to_string()).is_err());
+        assert!(CpuList::parse(&format!("0-{}", usize::MAX)).is_err());
+        assert!(serde_yaml::from_str::<CpuList>(&MAX_CPUS.to_string()).is_err());
+    }
+
+    /// Tests that formatting collapses consecutive CPUs into ranges, the way the kernel does.
+    #[test]
+    fn test_display_collapses_ranges() {
+        let list = CpuList::from_cpus([0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(list.to_string(), "0-3,8,10-11");
+        assert_eq!(CpuList::parse(&list.to_string()).unwrap(), list);
+    }
+
+    /// Tests that a cpulist can be written as a string or a plain number in YAML.
+    #[test]
+    fn test_deserialize_yaml() {
+        let list: CpuList = serde_yaml::from_str("\"0-1,4\"").unwrap();
+        assert_eq!(list.to_string(), "0-1,4");
+        let list: CpuList = serde_yaml::from_str("2").unwrap();
+        assert_eq!(list.to_string(), "2");
+        assert!(serde_yaml::from_str::<CpuList>("\"4-2\"").is_err());
+    }
+}
@@ -0,0 +1,269 @@
+/// The interpreters whose scripts can be matched, with the way their command lines are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interpreter {
+    Python,
+    Node,
+    Java,
+    Perl,
+}
+
+impl Interpreter {
+    /// Recognizes an interpreter by the base name of its program, e.g. `python3.12`.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The first element of the argument vector.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Interpreter)` if the program is a known interpreter.
+    /// * `None` otherwise.
+    fn detect(program: &str) -> Option<Self> {
+        let name = base_name(program);
+        let versioned = |prefix: &str| {
+            name.strip_prefix(prefix)
+                .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        };
+
+        if versioned("python") || versioned("pypy") {
+            Some(Interpreter::Python)
+        } else if name == "node" || name == "nodejs" {
+            Some(Interpreter::Node)
+        } else if name == "java" {
+            Some(Interpreter::Java)
+        } else if versioned("perl") {
+            Some(Interpreter::Perl)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the options whose value follows as a separate argument.
+    fn options_with_value(self) -> &'static [&'static str] {
+        match self {
+            Interpreter::Python => &["-W", "-X", "--check-hash-based-pycs"],
+            Interpreter::Node => &[
+                "-r",
+                "--require",
+                "--import",
+                "--loader",
+                "--experimental-loader",
+                "--title",
+                "-C",
+                "--conditions",
+            ],
+            Interpreter::Java => &[
+                "-cp",
+                "-classpath",
+                "--class-path",
+                "-p",
+                "--module-path",
+                "--upgrade-module-path",
+                "--add-modules",
+                "--add-reads",
+                "--add-exports",
+                "--add-opens",
+                "--limit-modules",
+                "--patch-module",
+                "--source",
+            ],
+            Interpreter::Perl => &["-I", "-M", "-m"],
+        }
+    }
+
+    /// Returns the options that run inline code instead of a script.
+    fn inline_code_options(self) -> &'static [&'static str] {
+        match self {
+            Interpreter::Python => &["-c"],
+            Interpreter::Node => &["-e", "--eval", "-p", "--print"],
+            Interpreter::Java => &[],
+            Interpreter::Perl => &["-e", "-E"],
+        }
+    }
+
+    /// Returns `true` if short options take their value attached, getopt style (`-mpip`).
+    fn attaches_short_values(self) -> bool {
+        matches!(self, Interpreter::Python | Interpreter::Perl)
+    }
+
+    /// Returns the options whose value is the program to run, e.g. `python -m module`.
+    fn program_options(self) -> &'static [&'static str] {
+        match self {
+            Interpreter::Python => &["-m"],
+            Interpreter::Node => &[],
+            Interpreter::Java => &["-jar", "-m", "--module"],
+            Interpreter::Perl => &[],
+        }
+    }
+}
+
+/// Finds the script or main class an interpreter was started with.
+///
+/// This is the first argument that is not an option, the module of `python -m module`, or
+/// the target of `java -jar` and `java -m`.
+///
+/// # Arguments
+///
+/// * `argv` - The arguments of the process, starting with the program name.
+///
+/// # Returns
+///
+/// * `Some(&str)` with the script, module or main class.
+/// * `None` if the program is not a known interpreter or runs inline code.
+pub fn script_for_argv(argv: &[String]) -> Option<&str> {
+    let (program, args) = argv.split_first()?;
+    let interpreter = Interpreter::detect(program)?;
+    let attached = interpreter.attaches_short_values();
+    let mut args = args.iter().map(String::as_str);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return args.next();
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            return Some(arg);
+        }
+        if let Some(program) = option_value(arg, interpreter.program_options(), attached, &mut args)
+        {
+            return program;
+        }
+        if option_value(arg, interpreter.inline_code_options(), attached, &mut args).is_some() {
+            return None;
+        }
+        option_value(arg, interpreter.options_with_value(), attached, &mut args);
+    }
+
+    None
+}
+
+/// Checks whether an argument is one of the given options and consumes its value.
+/// Long options may carry the value after `=`, short options may carry it attached
+/// (`-mhttp.server`) if the interpreter allows it.
+///
+/// # Arguments
+///
+/// * `arg` - The current argument.
+/// * `options` - The options to look for.
+/// * `short_attached` - Whether short options may carry their value attached.
+/// * `args` - The remaining arguments, advanced if the value is a separate argument.
+///
+/// # Returns
+///
+/// * `Some(Option<&str>)` with the value if `arg` is one of the options.
+/// * `None` otherwise.
+fn option_value<'a>(
+    arg: &'a str,
+    options: &[&str],
+    short_attached: bool,
+    args: &mut impl Iterator<Item = &'a str>,
+) -> Option<Option<&'a str>> {
+    for option in options {
+        if arg == *option {
+            return Some(args.next());
+        }
+        let attached = if option.starts_with("--") {
+            arg.strip_prefix(option)
+                .and_then(|rest| rest.strip_prefix('='))
+        } else if short_attached && option.len() == 2 {
+            arg.strip_prefix(option)
+        } else {
+            None
+        };
+        if let Some(value) = attached {
+            return Some(Some(value));
+        }
+    }
+    None
+}
+
+/// Returns the last component of a path.
+///
+/// # Arguments
+///
+/// * `path` - The path.
+///
+/// # Returns
+///
+/// * The part after the last `/`, or the whole path if it contains none.
+pub fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper function to find the script of a command line given as string literals.
+    fn script(args: &[&str]) -> Option<String> {
+        let argv: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        script_for_argv(&argv).map(str::to_string)
+    }
+
+    /// Tests that Python scripts and modules are found behind options.
+    #[test]
+    fn test_python_scripts() {
+        let manage = Some(String::from("./manage.py"));
+        assert_eq!(script(&["python3", "./manage.py", "runserver"]), manage);
+        assert_eq!(
+            script(&["/usr/bin/python3.12", "-u", "-W", "ignore", "./manage.py"]),
+            manage
+        );
+        assert_eq!(
+            script(&["python", "-m", "http.server"]),
+            Some(String::from("http.server"))
+        );
+        assert_eq!(
+            script(&["python3", "-mpip", "install"]),
+            Some(String::from("pip"))
+        );
+        assert_eq!(script(&["python3", "-c", "print(1)"]), None);
+        assert_eq!(script(&["python3"]), None);
+    }
+
+    /// Tests that Java main classes, jars and modules are found behind class path options.
+    #[test]
+    fn test_java_main_class() {
+        assert_eq!(
+            script(&[
+                "java",
+                "-Xmx2g",
+                "-cp",
+                "a.jar:b.jar",
+                "org.example.Main",
+                "arg"
+            ]),
+            Some(String::from("org.example.Main"))
+        );
+        assert_eq!(
+            script(&["java", "-Dx=y", "-jar", "/opt/app.jar"]),
+            Some(String::from("/opt/app.jar"))
+        );
+        assert_eq!(
+            script(&["java", "--module=app/org.example.Main"]),
+            Some(String::from("app/org.example.Main"))
+        );
+    }
+
+    /// Tests that Node and Perl scripts are found and inline code is not mistaken for one.
+    #[test]
+    fn test_node_and_perl_scripts() {
+        assert_eq!(
+            script(&["node", "--require", "dotenv/config", "server.js"]),
+            Some(String::from("server.js"))
+        );
+        assert_eq!(script(&["node", "-e", "1"]), None);
+        assert_eq!(
+            script(&["perl", "-I", "lib", "-w", "bin/tool.pl"]),
+            Some(String::from("bin/tool.pl"))
+        );
+        assert_eq!(script(&["perl", "-e", "print 1"]), None);
+    }
+
+    /// Tests that programs which are not interpreters have no script.
+    #[test]
+    fn test_unknown_program() {
+        assert_eq!(script(&["/usr/bin/make", "all"]), None);
+        assert_eq!(script(&["pythonista", "x.py"]), None);
+        assert_eq!(script(&[]), None);
+    }
+}
@@ -167,6 +167,7 @@ pub fn init_logger(log_level: Option<&str>) {
 /// This function creates two layers:
 /// - A `journald_layer` that sends logs to the system journal.
 /// - A `fmt_layer` that prints logs to stdout.
+///
 /// The two layers are then added to the `tracing_subscriber::registry` along with the level filter.
 fn init_with_journald_and_foreground(level: Level) {
     let fmt_layer = fmt::layer().with_target(false);
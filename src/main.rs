@@ -1,13 +1,20 @@
 use anyhow::Result;
 use clap::{Arg, Command};
-use serde_yaml;
 use std::process::exit;
 
 mod adjuster;
+mod cgroup;
 mod config;
+mod connector;
+mod container;
+mod cpulist;
+mod interpreter;
 mod logger;
 mod matcher;
 mod monitor;
+mod process;
+mod subtree;
+mod tracker;
 
 use crate::logger::init_logger;
 
@@ -1,4 +1,15 @@
-use crate::config::{Config, ProcessConfig};
+use crate::cgroup::units_in_path;
+use crate::config::{
+    Config, ContainerMatcher, MatcherConfig, OwnerMatcher, ProcessConfig, RuleSource, UidKind,
+};
+use crate::interpreter::{base_name, script_for_argv};
+use crate::process::{Credentials, ProcessCache, ProcessInfo};
+use crate::{debug, info};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process;
 
 /// A struct that handles matching processes against the configuration.
 ///
@@ -6,6 +17,9 @@ use crate::config::{Config, ProcessConfig};
 /// specified criteria (e.g., binary name, command-line arguments).
 pub struct ProcessMatcher<'a> {
     config: &'a Config,
+    /// The protected processes a rule was refused for, by PID, start time and rule name.
+    /// Only the first refusal is logged at info level.
+    refused: RefCell<HashSet<(i32, u64, String)>>,
 }
 
 impl<'a> ProcessMatcher<'a> {
@@ -19,24 +33,190 @@ impl<'a> ProcessMatcher<'a> {
     ///
     /// * A new instance of `ProcessMatcher`.
     pub fn new(config: &'a Config) -> Self {
-        ProcessMatcher { config }
+        ProcessMatcher {
+            config,
+            refused: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Forgets the refusals logged for a process that exited.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    pub fn forget(&self, pid: i32) {
+        self.refused
+            .borrow_mut()
+            .retain(|(refused, _, _)| *refused != pid);
+    }
+
+    /// Forgets the refusals logged for processes that are no longer running.
+    ///
+    /// # Arguments
+    ///
+    /// * `running` - The PIDs of all currently running processes.
+    pub fn retain(&self, running: &HashSet<i32>) {
+        self.refused
+            .borrow_mut()
+            .retain(|(pid, _, _)| running.contains(pid));
     }
 
-    /// Matches a command string against the configuration's process settings.
+    /// Matches a process against the configuration's process settings.
+    ///
+    /// Rules that match a protected process are refused unless they set `allow_protected`.
+    /// If several rules match, the one with the highest `priority` wins. Ties are broken by
+    /// specificity, then by source (user configurations over the global one), then by the
+    /// position in the configuration file. The losing rules are logged at debug level.
     ///
     /// # Arguments
     ///
-    /// * `command` - The command string to match against the configuration.
-    /// * `process_owner` - The owner of the process being checked (e.g., username).
+    /// * `info` - The attributes of the process to match against the configuration.
+    /// * `cache` - The processes read during the current scan, used to look up ancestors.
     ///
     /// # Returns
     ///
-    /// * `Some(&ProcessConfig)` if the command matches a process configuration.
+    /// * `Some(&ProcessConfig)` if the process matches a process configuration.
     /// * `None` if no match is found.
-    pub fn match_command(&self, command: &str, process_owner: &str) -> Option<&ProcessConfig> {
-        for process_config in &self.config.process {
-            if self.is_command_matched(command, process_owner, process_config) {
-                return Some(process_config);
+    pub fn match_process(
+        &self,
+        info: &ProcessInfo,
+        cache: &ProcessCache,
+    ) -> Option<&ProcessConfig> {
+        let mut candidates: Vec<(usize, &ProcessConfig)> = self
+            .config
+            .process
+            .iter()
+            .enumerate()
+            .filter(|(_, process_config)| self.is_command_matched(info, process_config, cache))
+            .filter(|(_, process_config)| self.is_allowed(info, process_config))
+            .collect();
+        candidates.sort_by_key(|&(index, process_config)| precedence(index, process_config));
+
+        let (_, winner) = *candidates.first()?;
+        for (_, loser) in &candidates[1..] {
+            debug!(
+                "Rule '{}' ({}, priority {}, specificity {}) also matches PID {} but rule '{}' ({}, priority {}, specificity {}) takes precedence",
+                loser.name,
+                loser.source,
+                loser.priority,
+                loser.specificity(),
+                info.pid,
+                winner.name,
+                winner.source,
+                winner.priority,
+                winner.specificity()
+            );
+        }
+        Some(winner)
+    }
+
+    /// Checks whether a process runs as the `owner` of a rule. Rules without an owner match
+    /// every process.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process.
+    /// * `process_config` - The rule to check the owner of.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the rule has no owner or the process runs as the owner.
+    /// * `false` otherwise.
+    pub fn is_owned(&self, info: &ProcessInfo, process_config: &ProcessConfig) -> bool {
+        process_config
+            .owner
+            .as_ref()
+            .is_none_or(|owner| self.match_owner(&info.credentials, owner))
+    }
+
+    /// Checks whether a rule may adjust a process. Processes on the `protect` list are only
+    /// adjusted by rules with `allow_protected: true`. The first refusal of a rule for a
+    /// process is logged at info level, repeated ones at debug level.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process.
+    /// * `process_config` - The rule that applies to the process.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the process is not protected or the rule overrides the protection.
+    /// * `false` if the rule must not adjust the process.
+    pub fn is_allowed(&self, info: &ProcessInfo, process_config: &ProcessConfig) -> bool {
+        if process_config.allow_protected {
+            return true;
+        }
+        match self.protection(info) {
+            Some(reason) => {
+                let first = self.refused.borrow_mut().insert((
+                    info.pid,
+                    info.starttime,
+                    process_config.name.clone(),
+                ));
+                if first {
+                    info!(
+                        "Refusing to apply rule '{}' to PID {}, it is protected as {}",
+                        process_config.name, info.pid, reason
+                    );
+                } else {
+                    debug!(
+                        "Refusing to apply rule '{}' to PID {}, it is protected as {}",
+                        process_config.name, info.pid, reason
+                    );
+                }
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Checks whether a process is on the `protect` list. reniced itself is always protected.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(String)` describing why the process is protected.
+    /// * `None` if the process is not protected.
+    fn protection(&self, info: &ProcessInfo) -> Option<String> {
+        let protect = &self.config.protect;
+
+        if info.pid == process::id() as i32 {
+            return Some(String::from("reniced itself"));
+        }
+        if protect.pids.contains(&info.pid) {
+            return Some(format!("PID {}", info.pid));
+        }
+        if info.kernel_thread {
+            let comm = info.comm().filter(|comm| {
+                protect
+                    .compiled_kernel_threads
+                    .iter()
+                    .any(|glob| glob.is_match(comm))
+            });
+            if let Some(comm) = comm {
+                return Some(format!("kernel thread {}", comm));
+            }
+        }
+        if let Some(exe) = info
+            .exe()
+            .filter(|exe| protect.compiled_exe.iter().any(|p| p == exe))
+        {
+            return Some(format!("executable {}", exe.display()));
+        }
+        if !protect.compiled_units.is_empty() {
+            let unit = info.cgroup().and_then(|path| {
+                units_in_path(path).find(|unit| {
+                    protect
+                        .compiled_units
+                        .iter()
+                        .any(|glob| glob.is_match(unit))
+                })
+            });
+            if let Some(unit) = unit {
+                return Some(format!("unit {}", unit));
             }
         }
         None
@@ -46,70 +226,93 @@ impl<'a> ProcessMatcher<'a> {
     ///
     /// # Arguments
     ///
-    /// * `process_config` - A reference to a `ProcessConfig` struct.
+    /// * `matcher` - A reference to the `MatcherConfig` being evaluated.
+    /// * `bin` - The `bin` value of the rule the matcher belongs to.
     ///
     /// # Returns
     ///
     /// * A `String` representing the match pattern. If `match_string` is set, it is returned.
     ///   Otherwise, the `bin` value is used with a trailing space.
-    fn get_pattern(&self, process_config: &ProcessConfig) -> String {
-        if let Some(match_string) = &process_config.matcher.match_string {
+    fn get_pattern(&self, matcher: &MatcherConfig, bin: &str) -> String {
+        if let Some(match_string) = &matcher.match_string {
             match_string.clone()
         } else {
-            format!("{} ", process_config.bin)
+            format!("{} ", bin)
         }
     }
 
-    /// Strips the path from the command if the `strip_path` option is enabled.
+    /// Returns the literal the `exact` and `comm` matcher types compare against.
     ///
     /// # Arguments
     ///
-    /// * `cmd` - The full command string.
-    /// * `pattern` - The pattern to locate in the command string.
+    /// * `matcher` - A reference to the `MatcherConfig` being evaluated.
+    /// * `bin` - The `bin` value of the rule the matcher belongs to.
     ///
     /// # Returns
     ///
-    /// * A `String` representing the command with the path stripped (if applicable).
-    fn strip_path_from_command(&self, cmd: &str, pattern: &String) -> String {
-        if let Some(first_space_index) = cmd.find(pattern) {
-            let rest_of_cmd = &cmd[first_space_index..].trim_start();
-            format!("{}", rest_of_cmd)
-        } else {
-            cmd.to_string()
-        }
+    /// * The `match_string` if it is set, the `bin` value otherwise.
+    fn get_literal<'c>(&self, matcher: &'c MatcherConfig, bin: &'c str) -> &'c str {
+        matcher.match_string.as_deref().unwrap_or(bin)
     }
 
-    /// Prepares the command for matching by optionally stripping the path.
+    /// Strips the directory from the program name if the `strip_path` option is enabled.
     ///
     /// # Arguments
     ///
-    /// * `cmd` - The full command string.
-    /// * `pattern` - The pattern used for matching.
-    /// * `matcher` - A reference to the `MatcherConfig` specifying match settings.
+    /// * `program` - The first argument of the process.
+    ///
+    /// # Returns
+    ///
+    /// * The file name of the program, or the program unchanged if it has none.
+    fn strip_path_from_program<'p>(&self, program: &'p str) -> &'p str {
+        Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program)
+    }
+
+    /// Joins the arguments of a process into the command line the `simple`, `exact`, `glob`
+    /// and `regex` matcher types compare against. Every argument is followed by a space, and
+    /// arguments that are empty or contain whitespace, quotes or backslashes are single-quoted
+    /// as in a shell, so `["a b", "c"]` and `["a", "b c"]` do not look the same.
+    ///
+    /// # Arguments
+    ///
+    /// * `argv` - The arguments of the process, starting with the program name.
+    /// * `strip_path` - Whether to strip the directory from the program name.
     ///
     /// # Returns
     ///
     /// * A `String` representing the prepared command string.
-    fn prepare_command(
-        &self,
-        cmd: &str,
-        pattern: &String,
-        matcher: &crate::config::MatcherConfig,
-    ) -> String {
-        let strip = matcher.strip_path.unwrap_or(false);
-
-        if strip {
-            self.strip_path_from_command(cmd, pattern)
-        } else {
-            cmd.to_string()
+    fn prepare_command(&self, argv: &[String], strip_path: bool) -> String {
+        let mut cmd = String::new();
+        for (index, arg) in argv.iter().enumerate() {
+            let arg = if index == 0 && strip_path {
+                self.strip_path_from_program(arg)
+            } else {
+                arg
+            };
+            if arg.is_empty()
+                || arg
+                    .chars()
+                    .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'))
+            {
+                cmd.push('\'');
+                cmd.push_str(&arg.replace('\'', r"'\''"));
+                cmd.push('\'');
+            } else {
+                cmd.push_str(arg);
+            }
+            cmd.push(' ');
         }
+        cmd
     }
 
     /// Matches a command against a simple matching type.
     ///
     /// # Arguments
     ///
-    /// * `cmd` - The full command string.
+    /// * `argv` - The arguments of the process, starting with the program name.
     /// * `pattern` - The pattern to match against.
     /// * `matcher` - A reference to the `MatcherConfig` specifying match settings.
     ///
@@ -117,68 +320,504 @@ impl<'a> ProcessMatcher<'a> {
     ///
     /// * `true` if the command matches the pattern.
     /// * `false` otherwise.
-    fn match_simple(
+    fn match_simple(&self, argv: &[String], pattern: &str, matcher: &MatcherConfig) -> bool {
+        let cmd_to_check = self.prepare_command(argv, matcher.strip_path.unwrap_or(false));
+        cmd_to_check.starts_with(pattern)
+    }
+
+    /// Matches a command against the regular expression compiled from `match_string`.
+    /// The expression may match anywhere in the command; use `^` and `$` to anchor it.
+    /// The trailing separator left by the command line's final NUL byte is ignored, so `$`
+    /// anchors to the end of the last argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The full command string.
+    /// * `matcher` - A reference to the `MatcherConfig` holding the compiled expression.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the expression matches the command.
+    /// * `false` otherwise, or if the expression was not compiled.
+    fn match_regex(&self, cmd: &str, matcher: &MatcherConfig) -> bool {
+        matcher
+            .compiled
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(cmd.trim_end()))
+    }
+
+    /// Matches a command that must equal the literal exactly, ignoring the trailing
+    /// separator left by the command line's final NUL byte.
+    ///
+    /// # Arguments
+    ///
+    /// * `argv` - The arguments of the process, starting with the program name.
+    /// * `literal` - The command to compare against.
+    /// * `matcher` - A reference to the `MatcherConfig` specifying match settings.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the (optionally path-stripped) command equals the literal.
+    /// * `false` otherwise.
+    fn match_exact(&self, argv: &[String], literal: &str, matcher: &MatcherConfig) -> bool {
+        let cmd_to_check = self.prepare_command(argv, matcher.strip_path.unwrap_or(false));
+        cmd_to_check.trim_end() == literal
+    }
+
+    /// Matches the command name of a process, as found in `/proc/{pid}/comm`.
+    ///
+    /// # Arguments
+    ///
+    /// * `comm` - The command name of the process, if it could be read.
+    /// * `literal` - The command name to compare against.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the command name equals the literal.
+    /// * `false` otherwise.
+    fn match_comm(&self, comm: Option<&str>, literal: &str) -> bool {
+        comm == Some(literal)
+    }
+
+    /// Matches the resolved executable of a process against the canonical path compiled from
+    /// `match_string`. Unlike the command line, the executable cannot be spoofed by the process.
+    ///
+    /// # Arguments
+    ///
+    /// * `exe` - The executable path of the process, if it could be read.
+    /// * `matcher` - A reference to the `MatcherConfig` holding the canonical path.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the paths are equal.
+    /// * `false` otherwise, or if the path was not compiled.
+    fn match_exe(&self, exe: Option<&Path>, matcher: &MatcherConfig) -> bool {
+        exe.is_some_and(|exe| matcher.exe.as_deref() == Some(exe))
+    }
+
+    /// Matches a command against the shell-style glob in `match_string`. The glob has to
+    /// match the whole command; `*` also matches `/` and spaces.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The full command string.
+    /// * `matcher` - A reference to the `MatcherConfig` holding the compiled glob.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the glob matches the command.
+    /// * `false` otherwise, or if the glob was not compiled.
+    fn match_glob(&self, cmd: &str, matcher: &MatcherConfig) -> bool {
+        self.match_regex(cmd, matcher)
+    }
+
+    /// Matches the argument vector of a process against the `argv0`, `args_contain`,
+    /// `arg_regex` and `script` options. Options that are not set always match.
+    ///
+    /// # Arguments
+    ///
+    /// * `argv` - The arguments of the process, starting with the program name.
+    /// * `matcher` - A reference to the `MatcherConfig` specifying the argv options.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if all set options match.
+    /// * `false` otherwise.
+    fn match_argv(&self, argv: &[String], matcher: &MatcherConfig) -> bool {
+        let (program, args) = match argv.split_first() {
+            Some((program, args)) => (program.as_str(), args),
+            None => return !matcher.has_argv_options(),
+        };
+
+        if let Some(argv0) = &matcher.argv0 {
+            let program = if argv0.contains('/') {
+                program
+            } else {
+                base_name(program)
+            };
+            if program != argv0 {
+                return false;
+            }
+        }
+
+        if let Some(expected) = &matcher.script {
+            let Some(script) = script_for_argv(argv) else {
+                return false;
+            };
+            let script = if expected.contains('/') {
+                script
+            } else {
+                base_name(script)
+            };
+            if script != expected {
+                return false;
+            }
+        }
+
+        if let Some(required) = &matcher.args_contain {
+            if !required.iter().all(|arg| args.contains(arg)) {
+                return false;
+            }
+        }
+
+        if let Some(regex) = &matcher.compiled_arg_regex {
+            let matched = match matcher.arg_index {
+                Some(index) => argv.get(index).is_some_and(|arg| regex.is_match(arg)),
+                None => args.iter().any(|arg| regex.is_match(arg)),
+            };
+            if !matched {
+                return false;
+            }
+        } else if matcher.arg_regex.is_some() {
+            return false;
+        }
+
+        true
+    }
+
+    /// Matches the cgroup v2 path of a process against the `cgroup` (or `cgroup_regex`) and
+    /// `unit` options. Options that are not set always match.
+    ///
+    /// # Arguments
+    ///
+    /// * `cgroup` - The cgroup path of the process, if it could be read.
+    /// * `matcher` - A reference to the `MatcherConfig` holding the compiled patterns.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if all set options match.
+    /// * `false` otherwise, or if an option is set and the cgroup path is unknown.
+    fn match_cgroup(&self, cgroup: Option<&str>, matcher: &MatcherConfig) -> bool {
+        if let Some(regex) = &matcher.compiled_cgroup {
+            if !cgroup.is_some_and(|path| regex.is_match(path)) {
+                return false;
+            }
+        }
+        if let Some(regex) = &matcher.compiled_unit {
+            if !cgroup.is_some_and(|path| units_in_path(path).any(|unit| regex.is_match(unit))) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Matches the container of a process against the `container` option.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process to check.
+    /// * `container` - A reference to the `ContainerMatcher` to evaluate.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the process runs in a container with the given runtime, ID and name.
+    /// * `false` otherwise, or if the name is required but cannot be looked up.
+    fn match_container(&self, info: &ProcessInfo, container: &ContainerMatcher) -> bool {
+        let Some(found) = info.container() else {
+            return false;
+        };
+        if container
+            .runtime
+            .is_some_and(|runtime| runtime != found.runtime)
+        {
+            return false;
+        }
+        if let Some(id) = &container.id {
+            if found.id.is_empty() || !found.id.starts_with(id.as_str()) {
+                return false;
+            }
+        }
+        if let Some(regex) = &container.compiled_name {
+            if !info
+                .container_name()
+                .is_some_and(|name| regex.is_match(name))
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Matches the environment of a process against the `env` option.
+    ///
+    /// # Arguments
+    ///
+    /// * `environ` - The environment of the process, if it could be read.
+    /// * `matcher` - A reference to the `MatcherConfig` holding the compiled value globs.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if every variable is set and its value matches the glob, if any.
+    /// * `false` otherwise, or if the environment could not be read.
+    fn match_env(
         &self,
-        cmd: &str,
-        pattern: &String,
-        matcher: &crate::config::MatcherConfig,
+        environ: Option<&HashMap<String, String>>,
+        matcher: &MatcherConfig,
     ) -> bool {
-        let cmd_to_check = self.prepare_command(cmd, pattern, matcher);
-        cmd_to_check.starts_with(pattern.as_str())
+        let Some(environ) = environ else {
+            return false;
+        };
+        matcher.compiled_env.iter().all(|(name, glob)| {
+            environ
+                .get(name)
+                .is_some_and(|value| glob.as_ref().is_none_or(|glob| glob.is_match(value)))
+        })
     }
 
-    /// Checks if a command matches the given process configuration.
+    /// Checks if the credentials of a process match an owner matcher. The user must be in
+    /// the resolved UID range and the group must be the primary group or one of the
+    /// supplementary groups. A user or group that could not be resolved never matches.
     ///
     /// # Arguments
     ///
-    /// * `cmd` - The full command string to check.
-    /// * `process_owner` - The owner of the process being checked.
+    /// * `credentials` - The user and group IDs of the process.
+    /// * `owner` - The resolved owner matcher.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the process runs as the user and group.
+    /// * `false` otherwise.
+    fn match_owner(&self, credentials: &Credentials, owner: &OwnerMatcher) -> bool {
+        let (uid, gid) = match owner.uid {
+            UidKind::Real => (credentials.uid, credentials.gid),
+            UidKind::Effective => (credentials.euid, credentials.egid),
+        };
+        if owner.user.is_some()
+            && !owner
+                .resolved_uids
+                .as_ref()
+                .is_some_and(|uids| uids.contains(&uid))
+        {
+            return false;
+        }
+        if owner.group.is_some()
+            && !owner
+                .resolved_gid
+                .is_some_and(|group| group == gid || credentials.groups.contains(&group))
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Checks if a process matches the given process configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process to check.
     /// * `process_config` - A reference to the `ProcessConfig` containing match criteria.
+    /// * `cache` - The processes read during the current scan, used to look up ancestors.
     ///
     /// # Returns
     ///
-    /// * `true` if the command matches the process configuration.
+    /// * `true` if the process matches the process configuration.
     /// * `false` otherwise.
     fn is_command_matched(
         &self,
-        cmd: &str,
-        process_owner: &str,
+        info: &ProcessInfo,
         process_config: &ProcessConfig,
+        cache: &ProcessCache,
     ) -> bool {
-        if let Some(config_owner) = &process_config.owner {
-            if config_owner != process_owner {
+        // Kernel threads are only ever matched by rules meant for them, and vice versa.
+        if info.kernel_thread != process_config.kernel_thread {
+            return false;
+        }
+        if !self.is_owned(info, process_config) {
+            return false;
+        }
+
+        self.is_matcher_matched(info, &process_config.matcher, &process_config.bin, cache)
+    }
+
+    /// Evaluates a matcher tree against a process. All criteria set on a node must match:
+    /// its `owner`, argv options, type, cgroup, container and env options, every matcher in `all`, at least one in `any`,
+    /// not the matcher in `not`, and the `parent` and `ancestor` matchers. Evaluation stops
+    /// at the first criterion that fails, so parents are only looked up when needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process to check.
+    /// * `matcher` - A reference to the `MatcherConfig` node to evaluate.
+    /// * `bin` - The `bin` value of the rule, used when a node has no `match_string`.
+    /// * `cache` - The processes read during the current scan, used to look up ancestors.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the process matches the node.
+    /// * `false` otherwise.
+    fn is_matcher_matched(
+        &self,
+        info: &ProcessInfo,
+        matcher: &MatcherConfig,
+        bin: &str,
+        cache: &ProcessCache,
+    ) -> bool {
+        if let Some(owner) = &matcher.owner {
+            if !self.match_owner(&info.credentials, owner) {
+                return false;
+            }
+        }
+        if !self.match_argv(&info.argv, matcher) || !self.match_type(info, matcher, bin) {
+            return false;
+        }
+        if (matcher.compiled_cgroup.is_some() || matcher.compiled_unit.is_some())
+            && !self.match_cgroup(info.cgroup(), matcher)
+        {
+            return false;
+        }
+        if let Some(container) = &matcher.container {
+            if !self.match_container(info, container) {
+                return false;
+            }
+        }
+        if matcher.env.is_some() && !self.match_env(info.environ(), matcher) {
+            return false;
+        }
+        if let Some(all) = &matcher.all {
+            if !all
+                .iter()
+                .all(|child| self.is_matcher_matched(info, child, bin, cache))
+            {
+                return false;
+            }
+        }
+        if let Some(any) = &matcher.any {
+            if !any
+                .iter()
+                .any(|child| self.is_matcher_matched(info, child, bin, cache))
+            {
+                return false;
+            }
+        }
+        if let Some(not) = &matcher.not {
+            if self.is_matcher_matched(info, not, bin, cache) {
+                return false;
+            }
+        }
+        if let Some(parent) = &matcher.parent {
+            let parent_info = info.ppid.and_then(|ppid| cache.get(ppid).ok());
+            if !parent_info.is_some_and(|parent_info| {
+                self.is_matcher_matched(&parent_info, parent, bin, cache)
+            }) {
+                return false;
+            }
+        }
+        if let Some(ancestor) = &matcher.ancestor {
+            if !self.match_ancestor(info, ancestor, bin, cache) {
                 return false;
             }
         }
+        true
+    }
 
-        let matcher = &process_config.matcher;
-        let pattern = self.get_pattern(process_config);
+    /// Walks up the parent chain of a process until an ancestor matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process whose ancestors are checked.
+    /// * `matcher` - A reference to the `MatcherConfig` an ancestor must match.
+    /// * `bin` - The `bin` value of the rule, used when a node has no `match_string`.
+    /// * `cache` - The processes read during the current scan.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the parent, grandparent or any earlier ancestor matches.
+    /// * `false` otherwise, or if the chain breaks because an ancestor cannot be read.
+    fn match_ancestor(
+        &self,
+        info: &ProcessInfo,
+        matcher: &MatcherConfig,
+        bin: &str,
+        cache: &ProcessCache,
+    ) -> bool {
+        let mut ppid = info.ppid;
+        for _ in 0..MAX_ANCESTOR_DEPTH {
+            let Some(ancestor) = ppid.and_then(|ppid| cache.get(ppid).ok()) else {
+                return false;
+            };
+            if self.is_matcher_matched(&ancestor, matcher, bin, cache) {
+                return true;
+            }
+            ppid = ancestor.ppid;
+        }
+        false
+    }
 
+    /// Matches a process against the type of a matcher node.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process to check.
+    /// * `matcher` - A reference to the `MatcherConfig` node to evaluate.
+    /// * `bin` - The `bin` value of the rule, used when the node has no `match_string`.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the node has no type (or `argv`) or the process matches its type.
+    /// * `false` otherwise, including for unknown types.
+    fn match_type(&self, info: &ProcessInfo, matcher: &MatcherConfig, bin: &str) -> bool {
         match matcher.r#type.as_str() {
-            "simple" => self.match_simple(cmd, &pattern, matcher),
+            "" | "argv" => true,
+            "simple" => self.match_simple(&info.argv, &self.get_pattern(matcher, bin), matcher),
+            "regex" => self.match_regex(&self.prepare_command(&info.argv, false), matcher),
+            "exact" => self.match_exact(&info.argv, self.get_literal(matcher, bin), matcher),
+            "comm" => self.match_comm(info.comm(), self.get_literal(matcher, bin)),
+            "comm_glob" => info
+                .comm()
+                .is_some_and(|comm| self.match_regex(comm, matcher)),
+            "glob" => self.match_glob(&self.prepare_command(&info.argv, false), matcher),
+            "exe" => self.match_exe(info.exe(), matcher),
             _ => false,
         }
     }
 }
 
+/// The maximum number of parents walked up for an `ancestor` matcher. PID namespaces and
+/// PID reuse can in theory produce a parent cycle; this guards against walking it forever.
+const MAX_ANCESTOR_DEPTH: usize = 1024;
+
+/// Returns the sort key of a matching rule; the rule with the smallest key takes precedence.
+///
+/// # Arguments
+///
+/// * `index` - The position of the rule in the merged configuration.
+/// * `process_config` - A reference to the rule.
+///
+/// # Returns
+///
+/// * A key ordering by priority and specificity (both descending), then user rules before
+///   global ones, then file order.
+fn precedence(index: usize, process_config: &ProcessConfig) -> impl Ord {
+    (
+        Reverse(process_config.priority),
+        Reverse(process_config.specificity()),
+        process_config.source == RuleSource::Global,
+        process_config.order,
+        index,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::{Config, MatcherConfig, ProcessConfig};
+    use crate::process::{ProcessCache, ProcessInfo};
 
     /// Helper function to create a sample `ProcessConfig` for testing.
     /// This is used to ensure consistency across tests.
     fn create_test_process_config() -> ProcessConfig {
         ProcessConfig {
             name: String::from("test_process"),
-            owner: Some(String::from("test_user")),
+            owner: Some(OwnerMatcher::user("test_user")),
             bin: String::from("/usr/bin/test"),
             nice: 10,
             matcher: MatcherConfig {
                 r#type: String::from("simple"),
                 match_string: Some(String::from("test_process")),
                 strip_path: Some(true),
+                ..Default::default()
             },
+            ..Default::default()
         }
     }
 
@@ -193,7 +832,7 @@ mod tests {
         let process_config = create_test_process_config();
         let matcher = ProcessMatcher::new(&binding);
 
-        let pattern = matcher.get_pattern(&process_config);
+        let pattern = matcher.get_pattern(&process_config.matcher, &process_config.bin);
         assert_eq!(pattern, "test_process");
     }
 
@@ -207,71 +846,107 @@ mod tests {
         process_config.matcher.match_string = None;
         let matcher = ProcessMatcher::new(&binding);
 
-        let pattern = matcher.get_pattern(&process_config);
+        let pattern = matcher.get_pattern(&process_config.matcher, &process_config.bin);
         assert_eq!(pattern, "/usr/bin/test ");
     }
 
-    /// Tests that `strip_path_from_command` correctly identifies and extracts the relevant portion
-    /// of the command when the specified pattern exists in the input command.
+    /// Tests that `strip_path_from_program` returns the file name of the program.
     ///
-    /// This verifies that path stripping works as intended for valid patterns.
+    /// This verifies that path stripping depends on the program alone, not on where the
+    /// pattern occurs in the command.
     #[test]
-    fn test_strip_path_from_command_with_valid_pattern() {
+    fn test_strip_path_from_program() {
         let binding = Config::default();
         let matcher = ProcessMatcher::new(&binding);
-        let cmd = "/usr/bin/test_process --arg value";
-        let pattern = String::from("test_process ");
 
-        let stripped = matcher.strip_path_from_command(cmd, &pattern);
-        assert_eq!(stripped, "test_process --arg value");
+        assert_eq!(
+            matcher.strip_path_from_program("/usr/bin/test_process"),
+            "test_process"
+        );
+        assert_eq!(
+            matcher.strip_path_from_program("test_process"),
+            "test_process"
+        );
+        assert_eq!(matcher.strip_path_from_program("/"), "/");
     }
 
-    /// Tests that `strip_path_from_command` returns the command unchanged
-    /// when the specified pattern is not found.
+    /// Tests that `prepare_command` strips the path of the program only when asked to, and
+    /// leaves paths in the arguments alone.
     ///
-    /// This ensures that the function gracefully handles cases where no matching pattern exists.
+    /// This verifies that path stripping behavior is configurable.
     #[test]
-    fn test_strip_path_from_command_with_invalid_pattern() {
+    fn test_prepare_command_with_strip_path() {
         let binding = Config::default();
         let matcher = ProcessMatcher::new(&binding);
-        let cmd = "/usr/bin/other_process --arg value";
-        let pattern = String::from("test_process");
+        let argv = argv(&["/usr/bin/test_process", "/tmp/file"]);
 
-        let stripped = matcher.strip_path_from_command(cmd, &pattern);
-        assert_eq!(stripped, cmd);
+        assert_eq!(
+            matcher.prepare_command(&argv, true),
+            "test_process /tmp/file "
+        );
+        assert_eq!(
+            matcher.prepare_command(&argv, false),
+            "/usr/bin/test_process /tmp/file "
+        );
     }
 
-    /// Tests that `prepare_command` correctly prepares the command by stripping the path
-    /// when the `strip_path` field is set to `true` in the matcher configuration.
+    /// Tests that `prepare_command` quotes arguments with spaces, quotes or backslashes, and
+    /// empty arguments.
     ///
-    /// This verifies that path stripping behavior is configurable.
+    /// This ensures that different argument vectors never join into the same command.
     #[test]
-    fn test_prepare_command_with_strip_path() {
+    fn test_prepare_command_quotes_arguments() {
         let binding = Config::default();
         let matcher = ProcessMatcher::new(&binding);
-        let cmd = "/usr/bin/test_process --arg value";
-        let pattern = String::from("test_process");
-        let mut matcher_config = MatcherConfig::default();
-        matcher_config.strip_path = Some(true);
 
-        let prepared_cmd = matcher.prepare_command(cmd, &pattern, &matcher_config);
-        assert_eq!(prepared_cmd, "test_process --arg value");
+        assert_eq!(
+            matcher.prepare_command(&argv(&["a b", "c"]), false),
+            "'a b' c "
+        );
+        assert_eq!(
+            matcher.prepare_command(&argv(&["a", "b c"]), false),
+            "a 'b c' "
+        );
+        assert_eq!(
+            matcher.prepare_command(&argv(&["a", "", "it's"]), false),
+            r"a '' 'it'\''s' "
+        );
     }
 
-    /// Tests that `prepare_command` returns the command unchanged
-    /// when the `strip_path` field is not set or set to `false` in the matcher configuration.
+    /// Tests that the `simple`, `exact`, `glob` and `regex` matcher types tell apart argument
+    /// vectors that only differ in where an argument with a space ends.
     ///
-    /// This ensures that the function does not modify commands unnecessarily.
+    /// This ensures that a process cannot match a rule by moving spaces between arguments.
     #[test]
-    fn test_prepare_command_without_strip_path() {
+    fn test_matchers_with_embedded_spaces() {
         let binding = Config::default();
         let matcher = ProcessMatcher::new(&binding);
-        let cmd = "/usr/bin/test_process --arg value";
-        let pattern = String::from("test_process");
+        let first = argv(&["a b", "c"]);
+        let second = argv(&["a", "b c"]);
+
         let matcher_config = MatcherConfig::default();
+        assert!(matcher.match_simple(&first, "'a b' c", &matcher_config));
+        assert!(!matcher.match_simple(&second, "'a b' c", &matcher_config));
+        assert!(matcher.match_exact(&second, "a 'b c'", &matcher_config));
+        assert!(!matcher.match_exact(&first, "a 'b c'", &matcher_config));
+
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("glob"),
+            match_string: Some(String::from("'a b' *")),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+        assert!(matcher.match_glob(&matcher.prepare_command(&first, false), &matcher_config));
+        assert!(!matcher.match_glob(&matcher.prepare_command(&second, false), &matcher_config));
 
-        let prepared_cmd = matcher.prepare_command(cmd, &pattern, &matcher_config);
-        assert_eq!(prepared_cmd, cmd);
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("regex"),
+            match_string: Some(String::from("^a 'b c'$")),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+        assert!(matcher.match_regex(&matcher.prepare_command(&second, false), &matcher_config));
+        assert!(!matcher.match_regex(&matcher.prepare_command(&first, false), &matcher_config));
     }
 
     /// Tests that `match_simple` correctly identifies a command as matching
@@ -282,28 +957,611 @@ mod tests {
     fn test_match_simple_with_matching_command() {
         let binding = Config::default();
         let matcher = ProcessMatcher::new(&binding);
-        let cmd = "/usr/bin/test_process --arg value";
-        let pattern = String::from("test_process");
-        let mut matcher_config = MatcherConfig::default();
-        matcher_config.strip_path = Some(true);
+        let argv = argv(&["/usr/bin/test_process", "--arg", "value"]);
+        let matcher_config = MatcherConfig {
+            strip_path: Some(true),
+            ..Default::default()
+        };
 
-        let is_matched = matcher.match_simple(cmd, &pattern, &matcher_config);
+        let is_matched = matcher.match_simple(&argv, "test_process", &matcher_config);
         assert!(is_matched);
     }
 
     /// Tests that `match_simple` correctly identifies a command as non-matching
-    /// when the command does not start with the expected pattern.
+    /// when the command does not start with the expected pattern, even if the pattern
+    /// occurs in a later argument.
     ///
     /// This ensures that the function does not produce false positives.
     #[test]
     fn test_match_simple_with_non_matching_command() {
         let binding = Config::default();
         let matcher = ProcessMatcher::new(&binding);
-        let cmd = "/usr/bin/other_process --arg value";
-        let pattern = String::from("test_process");
-        let matcher_config = MatcherConfig::default();
+        let argv = argv(&["/usr/bin/other_process", "/usr/bin/test_process"]);
+        let mut matcher_config = MatcherConfig::default();
+
+        assert!(!matcher.match_simple(&argv, "test_process", &matcher_config));
+        matcher_config.strip_path = Some(true);
+        assert!(!matcher.match_simple(&argv, "test_process", &matcher_config));
+    }
+
+    /// Tests that `match_regex` matches commands against the compiled `match_string`,
+    /// including anchored expressions.
+    ///
+    /// This ensures that the regex matcher type works as documented.
+    #[test]
+    fn test_match_regex() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("regex"),
+            match_string: Some(String::from(r"^/usr/bin/python3 .*manage\.py")),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+
+        assert!(matcher.match_regex("/usr/bin/python3 ./manage.py runserver", &matcher_config));
+        assert!(!matcher.match_regex("/usr/bin/python3 ./other.py", &matcher_config));
+        assert!(!matcher.match_regex("vim /usr/bin/python3 manage.py", &matcher_config));
+
+        matcher_config.match_string = Some(String::from("^/usr/bin/foo --bar$"));
+        matcher_config.compile().unwrap();
+        assert!(matcher.match_regex("/usr/bin/foo --bar ", &matcher_config));
+        assert!(!matcher.match_regex("/usr/bin/foo --bar --baz ", &matcher_config));
+    }
+
+    /// Tests that `match_regex` never matches when the expression was not compiled.
+    ///
+    /// This ensures that an uncompiled matcher fails closed.
+    #[test]
+    fn test_match_regex_without_compiled_pattern() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let matcher_config = MatcherConfig {
+            r#type: String::from("regex"),
+            match_string: Some(String::from(".*")),
+            ..Default::default()
+        };
+
+        assert!(!matcher.match_regex("anything", &matcher_config));
+    }
+
+    /// Tests that `match_exact` requires the whole command to equal the literal, and that
+    /// `strip_path` ignores the directory of the program.
+    ///
+    /// This ensures that arguments cannot be appended to an exact match.
+    #[test]
+    fn test_match_exact() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("exact"),
+            ..Default::default()
+        };
+
+        let foo = argv(&["/usr/bin/foo", "--bar"]);
+        assert!(matcher.match_exact(&foo, "/usr/bin/foo --bar", &matcher_config));
+        assert!(!matcher.match_exact(
+            &argv(&["/usr/bin/foo", "--bar", "--baz"]),
+            "/usr/bin/foo --bar",
+            &matcher_config
+        ));
+        assert!(!matcher.match_exact(&foo, "foo --bar", &matcher_config));
+
+        matcher_config.strip_path = Some(true);
+        assert!(matcher.match_exact(&foo, "foo --bar", &matcher_config));
+    }
+
+    /// Tests that `match_comm` compares the command name exactly and never matches a
+    /// process whose command name could not be read.
+    ///
+    /// This ensures that the comm matcher type is not a prefix match.
+    #[test]
+    fn test_match_comm() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+
+        assert!(matcher.match_comm(Some("kworker"), "kworker"));
+        assert!(!matcher.match_comm(Some("kworker/0:1"), "kworker"));
+        assert!(!matcher.match_comm(None, "kworker"));
+    }
+
+    /// Tests that `match_glob` matches the whole command against a compiled shell-style glob.
+    ///
+    /// This ensures that `*`, `?` and character classes behave like in a shell, except that
+    /// `*` also crosses `/` and spaces.
+    #[test]
+    fn test_match_glob() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("glob"),
+            match_string: Some(String::from("*/bin/python3* */manage.py*")),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+
+        assert!(matcher.match_glob(
+            "/usr/bin/python3 /srv/app/manage.py runserver ",
+            &matcher_config
+        ));
+        assert!(matcher.match_glob("/usr/local/bin/python3.12 ./manage.py ", &matcher_config));
+        assert!(!matcher.match_glob("/usr/bin/python3 ./other.py ", &matcher_config));
+
+        matcher_config.match_string = Some(String::from("/usr/bin/cc[!0-9]?"));
+        matcher_config.compile().unwrap();
+        assert!(matcher.match_glob("/usr/bin/cc-x ", &matcher_config));
+        assert!(!matcher.match_glob("/usr/bin/cc1x ", &matcher_config));
+        assert!(!matcher.match_glob("/usr/bin/cc-xy ", &matcher_config));
+    }
+
+    /// Tests that `match_exe` compares the executable with the canonical path from the
+    /// configuration, so symlinks in the configured path are resolved.
+    ///
+    /// This ensures that the exe matcher type matches what the kernel reports.
+    #[test]
+    fn test_match_exe() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let dir = std::env::temp_dir().join(format!("reniced-exe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("tool");
+        std::fs::write(&target, "").unwrap();
+        let link = dir.join("tool-link");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("exe"),
+            match_string: Some(link.display().to_string()),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+
+        let target = std::fs::canonicalize(&target).unwrap();
+        assert!(matcher.match_exe(Some(&target), &matcher_config));
+        assert!(!matcher.match_exe(Some(&link), &matcher_config));
+        assert!(!matcher.match_exe(None, &matcher_config));
+
+        matcher_config.match_string = Some(String::from("tool"));
+        assert!(matcher_config.compile().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Helper function to build an argument vector from string literals.
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Tests that `match_argv` tells Electron helper processes apart by their arguments.
+    ///
+    /// This ensures that `argv0` compares base names and `args_contain` requires whole
+    /// arguments.
+    #[test]
+    fn test_match_argv_electron_helpers() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("argv"),
+            argv0: Some(String::from("electron")),
+            args_contain: Some(vec![String::from("--type=renderer")]),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+
+        let renderer = argv(&["/opt/app/electron", "--type=renderer", "--lang=en"]);
+        let gpu = argv(&["/opt/app/electron", "--type=gpu-process"]);
+        let main = argv(&["/opt/app/electron", "/opt/app/main.js --type=renderer"]);
+        assert!(matcher.match_argv(&renderer, &matcher_config));
+        assert!(!matcher.match_argv(&gpu, &matcher_config));
+        assert!(!matcher.match_argv(&main, &matcher_config));
+        assert!(!matcher.match_argv(&[], &matcher_config));
+    }
+
+    /// Tests that `arg_regex` matches the argument at `arg_index`, or any argument without it.
+    ///
+    /// This ensures that argument boundaries are respected, unlike on the joined command line.
+    #[test]
+    fn test_match_argv_arg_regex() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let mut matcher_config = MatcherConfig {
+            r#type: String::from("argv"),
+            arg_regex: Some(String::from("^manage\\.py$")),
+            ..Default::default()
+        };
+        matcher_config.compile().unwrap();
+
+        assert!(matcher.match_argv(&argv(&["python3", "-u", "manage.py"]), &matcher_config));
+        assert!(!matcher.match_argv(&argv(&["python3", "-u manage.py"]), &matcher_config));
+
+        matcher_config.arg_index = Some(1);
+        matcher_config.compile().unwrap();
+        assert!(matcher.match_argv(&argv(&["python3", "manage.py"]), &matcher_config));
+        assert!(!matcher.match_argv(&argv(&["python3", "-u", "manage.py"]), &matcher_config));
+    }
+
+    /// Tests that the `script` option matches the script an interpreter runs, not the
+    /// interpreter itself.
+    ///
+    /// This ensures that a rule for one Python script does not catch every Python process.
+    #[test]
+    fn test_match_argv_script() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let matcher_config = MatcherConfig {
+            r#type: String::from("argv"),
+            script: Some(String::from("manage.py")),
+            ..Default::default()
+        };
+
+        let manage = argv(&["/usr/bin/python3", "-u", "/srv/app/manage.py", "runserver"]);
+        let other = argv(&["/usr/bin/python3", "/srv/app/worker.py", "manage.py"]);
+        let not_interpreted = argv(&["/srv/app/manage.py"]);
+        assert!(matcher.match_argv(&manage, &matcher_config));
+        assert!(!matcher.match_argv(&other, &matcher_config));
+        assert!(!matcher.match_argv(&not_interpreted, &matcher_config));
+    }
+
+    /// Helper function to parse a rule from YAML and compile its matcher.
+    fn rule(yaml: &str) -> ProcessConfig {
+        let mut rule: ProcessConfig = serde_yaml::from_str(yaml).unwrap();
+        rule.matcher.compile().unwrap();
+        rule
+    }
+
+    /// Helper function to create the credentials of a process run by a user with the
+    /// primary group of the same ID.
+    fn user(uid: u32) -> Credentials {
+        Credentials {
+            uid,
+            euid: uid,
+            gid: uid,
+            egid: uid,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Tests that composite matchers combine their children: python scripts owned by `ci`,
+    /// but not the CI agent itself.
+    ///
+    /// This ensures that `all`, `any` and `not` nest with leaves of any type.
+    #[test]
+    fn test_composite_matcher() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let rule = rule(
+            "
+name: ci-python
+bin: python3
+nice: 10
+matcher:
+  all:
+    - { owner: 1001 }
+    - any:
+        - { type: simple, strip_path: true }
+        - { type: glob, match_string: '*/bin/python3.* *' }
+  not: { type: argv, script: agent.py }
+",
+        );
+        let process = |args: &[&str], uid: u32| ProcessInfo::new(-1, argv(args), user(uid));
+
+        let job = process(&["/usr/bin/python3", "build.py"], 1001);
+        let versioned = process(&["/usr/bin/python3.12", "build.py"], 1001);
+        let agent = process(&["/usr/bin/python3", "/opt/ci/agent.py"], 1001);
+        let other_user = process(&["/usr/bin/python3", "build.py"], 1002);
+        let other_program = process(&["/usr/bin/perl", "build.pl"], 1001);
+
+        let cache = ProcessCache::new();
+        assert!(matcher.is_command_matched(&job, &rule, &cache));
+        assert!(matcher.is_command_matched(&versioned, &rule, &cache));
+        assert!(!matcher.is_command_matched(&agent, &rule, &cache));
+        assert!(!matcher.is_command_matched(&other_user, &rule, &cache));
+        assert!(!matcher.is_command_matched(&other_program, &rule, &cache));
+    }
+
+    /// Tests that owners match by UID range, by primary or supplementary group and by real
+    /// or effective UID, and that unknown names never match, not even root's processes.
+    ///
+    /// This ensures that UIDs without a name, such as those mapped into containers, are
+    /// not mistaken for root.
+    #[test]
+    fn test_match_owner() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let owner = |yaml: &str| {
+            let mut owner: OwnerMatcher = serde_yaml::from_str(yaml).unwrap();
+            owner.resolve();
+            owner
+        };
+        let container_user = user(100_999);
+        let setuid = Credentials {
+            euid: 0,
+            ..user(1000)
+        };
+        let video_member = Credentials {
+            groups: vec![39, 44],
+            ..user(1000)
+        };
+
+        let range = owner("'100000-165535'");
+        assert!(matcher.match_owner(&container_user, &range));
+        assert!(!matcher.match_owner(&user(1000), &range));
+
+        let group = owner("{ group: 44 }");
+        assert!(matcher.match_owner(&video_member, &group));
+        assert!(matcher.match_owner(&user(44), &group));
+        assert!(!matcher.match_owner(&user(1000), &group));
+
+        let real = owner("root");
+        let effective = owner("{ user: 0, uid: effective }");
+        assert!(!matcher.match_owner(&setuid, &real));
+        assert!(matcher.match_owner(&setuid, &effective));
+        assert!(matcher.match_owner(&user(0), &real));
+
+        let unknown = owner("{ user: no-such-user-reniced, group: no-such-group-reniced }");
+        assert!(!matcher.match_owner(&user(0), &unknown));
+        assert!(!matcher.match_owner(&container_user, &unknown));
+    }
+
+    /// Tests that kernel threads are only matched by rules with `kernel_thread: true`, and
+    /// that such rules never match user space processes.
+    ///
+    /// This ensures that ordinary rules cannot renice kworkers by accident.
+    #[test]
+    fn test_kernel_thread_guard() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let cache = ProcessCache::new();
+        let ordinary =
+            rule("{ name: any, bin: x, nice: 5, matcher: { type: glob, match_string: '*' } }");
+        let kernel = rule(
+            "{ name: kthreads, bin: x, nice: 5, kernel_thread: true, matcher: { type: glob, match_string: '*' } }",
+        );
+        let mut kworker = ProcessInfo::new(-1, Vec::new(), user(0));
+        kworker.kernel_thread = true;
+        let process = ProcessInfo::new(-1, argv(&["make"]), user(0));
+
+        assert!(!matcher.is_command_matched(&kworker, &ordinary, &cache));
+        assert!(matcher.is_command_matched(&kworker, &kernel, &cache));
+        assert!(matcher.is_command_matched(&process, &ordinary, &cache));
+        assert!(!matcher.is_command_matched(&process, &kernel, &cache));
+    }
+
+    /// Tests that rules are refused for PID 1 and reniced itself unless they set
+    /// `allow_protected`, and that the `protect` list can be replaced.
+    ///
+    /// This ensures that a broad rule cannot renice the init system or the daemon.
+    #[test]
+    fn test_protected_processes() {
+        let yaml = "
+process:
+- { name: broad, bin: x, nice: 19, matcher: { type: glob, match_string: '*' } }
+";
+        let config = config(yaml);
+        let matcher = ProcessMatcher::new(&config);
+        let cache = ProcessCache::new();
+        let init = ProcessInfo::new(1, argv(&["/sbin/init"]), user(0));
+        let this = ProcessInfo::new(process::id() as i32, argv(&["reniced"]), user(0));
+        let other = ProcessInfo::new(-1, argv(&["make"]), user(0));
+
+        assert!(matcher.match_process(&init, &cache).is_none());
+        assert!(matcher.match_process(&this, &cache).is_none());
+        assert!(matcher.match_process(&other, &cache).is_some());
+
+        let mut config = config;
+        config.process[0].allow_protected = true;
+        let matcher = ProcessMatcher::new(&config);
+        assert!(matcher.match_process(&init, &cache).is_some());
+
+        let config = self::config(&format!("{}protect: {{ pids: [] }}", yaml));
+        let matcher = ProcessMatcher::new(&config);
+        assert!(matcher.match_process(&init, &cache).is_some());
+        assert!(matcher.match_process(&this, &cache).is_none());
+    }
+
+    /// Tests that refusals are remembered per process and rule until the process exits.
+    ///
+    /// This ensures that a protected process only triggers one info message per rule.
+    #[test]
+    fn test_refusals_are_remembered() {
+        let config = config("process:\n- { name: broad, bin: x, nice: 19, matcher: { type: glob, match_string: '*' } }\n");
+        let matcher = ProcessMatcher::new(&config);
+        let cache = ProcessCache::new();
+        let init = ProcessInfo::new(1, argv(&["/sbin/init"]), user(0));
+
+        assert!(matcher.match_process(&init, &cache).is_none());
+        assert!(matcher.match_process(&init, &cache).is_none());
+        assert_eq!(matcher.refused.borrow().len(), 1);
+
+        matcher.retain(&HashSet::from([1]));
+        assert_eq!(matcher.refused.borrow().len(), 1);
+        matcher.forget(1);
+        assert!(matcher.refused.borrow().is_empty());
+
+        assert!(matcher.match_process(&init, &cache).is_none());
+        matcher.retain(&HashSet::new());
+        assert!(matcher.refused.borrow().is_empty());
+    }
+
+    /// Tests that `comm_glob` matches the command name of a process against a glob.
+    ///
+    /// This ensures that numbered kernel threads such as `ksoftirqd/3` can be matched.
+    #[test]
+    fn test_comm_glob_matcher() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let cache = ProcessCache::new();
+        let this = ProcessInfo::read(std::process::id() as i32).unwrap();
+
+        let matching =
+            rule("{ name: self, bin: x, nice: 0, matcher: { type: comm_glob, match_string: 'reniced*' } }");
+        let other =
+            rule("{ name: ksoftirqd, bin: x, nice: 0, matcher: { type: comm_glob, match_string: 'ksoftirqd/*' } }");
+        assert!(matcher.is_command_matched(&this, &matching, &cache));
+        assert!(!matcher.is_command_matched(&this, &other, &cache));
+    }
+
+    /// Tests that matchers, `all` and `any` without any criterion are rejected, at any depth.
+    ///
+    /// This ensures that a typo cannot turn a rule into one that matches every process.
+    #[test]
+    fn test_composite_matcher_rejects_empty_nodes() {
+        let mut config: MatcherConfig = serde_yaml::from_str("{}").unwrap();
+        assert!(config.compile().is_err());
+
+        let mut config: MatcherConfig =
+            serde_yaml::from_str("{ type: comm, not: { any: [] } }").unwrap();
+        assert!(config.compile().is_err());
+
+        let mut config: MatcherConfig = serde_yaml::from_str("{ all: [] }").unwrap();
+        assert!(config.compile().is_err());
+
+        let mut config: MatcherConfig =
+            serde_yaml::from_str("{ type: comm, not: { all: [ {} ] } }").unwrap();
+        assert!(config.compile().is_err());
+    }
+
+    /// Helper function to create a configuration from YAML rules with prepared matchers.
+    fn config(yaml: &str) -> Config {
+        let mut config: Config = serde_yaml::from_str(yaml).unwrap();
+        for (order, rule) in config.process.iter_mut().enumerate() {
+            rule.matcher.compile().unwrap();
+            rule.order = order;
+        }
+        config
+    }
+
+    /// Tests that the winner among several matching rules is chosen by priority, then
+    /// specificity, then source, then file order.
+    ///
+    /// This ensures that the result does not depend on the order rules were merged in.
+    #[test]
+    fn test_match_process_precedence() {
+        let mut config = config(
+            "
+process:
+- { name: generic, bin: make, nice: 5, matcher: { type: simple } }
+- { name: specific, bin: make, nice: 10, matcher: { type: simple, owner: 1001 } }
+- { name: also-generic, bin: make, nice: 15, matcher: { type: glob, match_string: 'make *' } }
+",
+        );
+        let make = ProcessInfo::new(-1, argv(&["make", "-j8"]), user(1001));
+        let winner = |config: &Config| {
+            ProcessMatcher::new(config)
+                .match_process(&make, &ProcessCache::new())
+                .map(|rule| rule.name.clone())
+        };
+
+        assert_eq!(winner(&config).as_deref(), Some("specific"));
+
+        config.process[2].priority = 1;
+        assert_eq!(winner(&config).as_deref(), Some("also-generic"));
+
+        config.process[2].priority = 0;
+        config.process[1].matcher.owner = None;
+        config.process[1].source = RuleSource::User(String::from("ci"));
+        assert_eq!(winner(&config).as_deref(), Some("specific"));
+
+        config.process[1].source = RuleSource::Global;
+        assert_eq!(winner(&config).as_deref(), Some("generic"));
+    }
+
+    /// Tests that `parent` only looks at the direct parent while `ancestor` walks up the whole
+    /// chain, using the processes in the cache.
+    ///
+    /// This ensures that compilers started by `make` through a shell are found.
+    #[test]
+    fn test_parent_and_ancestor_matchers() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let cache = ProcessCache::new();
+        let process = |pid: i32, ppid: i32, args: &[&str]| {
+            let mut info = ProcessInfo::new(pid, argv(args), user(1001));
+            info.ppid = Some(ppid);
+            info
+        };
+        cache.insert(process(-10, -1, &["/usr/bin/make", "-j8"]));
+        cache.insert(process(-20, -10, &["/bin/sh", "-c", "cc -c a.c"]));
+        let compiler = process(-30, -20, &["/usr/bin/cc", "-c", "a.c"]);
+
+        let ancestor =
+            rule("{ name: builds, bin: cc, nice: 10, matcher: { ancestor: { argv0: make } } }");
+        let parent =
+            rule("{ name: builds, bin: cc, nice: 10, matcher: { parent: { argv0: make } } }");
+        let shell_parent =
+            rule("{ name: builds, bin: cc, nice: 10, matcher: { parent: { argv0: sh } } }");
+
+        assert!(matcher.is_command_matched(&compiler, &ancestor, &cache));
+        assert!(!matcher.is_command_matched(&compiler, &parent, &cache));
+        assert!(matcher.is_command_matched(&compiler, &shell_parent, &cache));
+
+        let orphan = process(-40, -99, &["/usr/bin/cc", "-c", "b.c"]);
+        assert!(!matcher.is_command_matched(&orphan, &ancestor, &cache));
+    }
+
+    /// Tests that `match_cgroup` matches cgroup globs and regexes against the whole path and
+    /// unit globs against any unit in it.
+    ///
+    /// This ensures that processes can be targeted by their systemd unit.
+    #[test]
+    fn test_match_cgroup_and_unit() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let flatpak = Some(
+            "/user.slice/user-1000.slice/user@1000.service/app.slice/app-flatpak-org.foo-12.scope",
+        );
+        let sshd = Some("/system.slice/sshd.service");
+
+        let mut by_path: MatcherConfig =
+            serde_yaml::from_str("{ cgroup: '/user.slice/*/app.slice/app-flatpak-org.foo*' }")
+                .unwrap();
+        by_path.compile().unwrap();
+        assert!(matcher.match_cgroup(flatpak, &by_path));
+        assert!(!matcher.match_cgroup(sshd, &by_path));
+        assert!(!matcher.match_cgroup(None, &by_path));
+
+        let mut by_unit: MatcherConfig = serde_yaml::from_str("{ unit: '*.service' }").unwrap();
+        by_unit.compile().unwrap();
+        assert!(matcher.match_cgroup(flatpak, &by_unit));
+        assert!(matcher.match_cgroup(sshd, &by_unit));
+
+        let mut by_scope: MatcherConfig = serde_yaml::from_str(
+            "{ unit: 'app-flatpak-*.scope', cgroup_regex: '^/user\\.slice/' }",
+        )
+        .unwrap();
+        by_scope.compile().unwrap();
+        assert!(matcher.match_cgroup(flatpak, &by_scope));
+        assert!(!matcher.match_cgroup(sshd, &by_scope));
+
+        let mut both: MatcherConfig =
+            serde_yaml::from_str("{ cgroup: '/*', cgroup_regex: '.*' }").unwrap();
+        assert!(both.compile().is_err());
+    }
+
+    /// Tests that `match_env` requires every listed variable, matching value globs and
+    /// accepting any value for `~`.
+    ///
+    /// This ensures that jobs tagged by their launcher can be matched.
+    #[test]
+    fn test_match_env() {
+        let binding = Config::default();
+        let matcher = ProcessMatcher::new(&binding);
+        let mut matcher_config: MatcherConfig =
+            serde_yaml::from_str("{ env: { JOB_CLASS: 'batch*', CI: ~ } }").unwrap();
+        matcher_config.compile().unwrap();
+        let environ = |vars: &[(&str, &str)]| -> HashMap<String, String> {
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
 
-        let is_matched = matcher.match_simple(cmd, &pattern, &matcher_config);
-        assert!(!is_matched);
+        let batch = environ(&[("JOB_CLASS", "batch-low"), ("CI", "")]);
+        let interactive = environ(&[("JOB_CLASS", "interactive"), ("CI", "1")]);
+        let untagged = environ(&[("JOB_CLASS", "batch")]);
+        assert!(matcher.match_env(Some(&batch), &matcher_config));
+        assert!(!matcher.match_env(Some(&interactive), &matcher_config));
+        assert!(!matcher.match_env(Some(&untagged), &matcher_config));
+        assert!(!matcher.match_env(None, &matcher_config));
     }
 }
@@ -1,16 +1,41 @@
 use anyhow::Result;
-use nix::unistd::{Uid, User};
-use std::collections::HashSet;
-use std::fs;
-use std::io::Read;
+use nix::libc;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::Duration;
+use tokio::time::{interval, Instant, MissedTickBehavior};
 
+use crate::config::{EnforcePolicy, ProcessConfig, Scope};
+use crate::connector::{ProcConnector, ProcEvent};
+use crate::process::{
+    get_comm_for_thread, get_fingerprint_for_pid, get_ppid_for_pid, get_running_processes,
+    get_starttime_for_pid, get_thread_for_pid, get_threads_for_pid, ProcessCache, ProcessIdentity,
+    ProcessInfo,
+};
+use crate::subtree::SubtreeTracker;
+use crate::tracker::{Change, ProcessTracker};
 use crate::{adjuster, config, matcher};
-use crate::{debug, error, warn};
+use crate::{debug, error, info, trace, warn};
+
+/// How often `/proc` is rescanned when the proc connector is not available.
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// How often managed processes with an `enforce` policy other than `once` are considered
+/// for a re-check.
+const ENFORCE_TICK: Duration = Duration::from_secs(1);
+
+/// The longest time until the next re-check of a managed process. Longer `interval` values
+/// cannot always be added to the current time; 100 years means the process is effectively
+/// never re-checked.
+const MAX_ENFORCE_INTERVAL: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
 
 /// The main event loop of the process monitoring daemon.
-/// This function continuously checks running processes, compares them with the previous state,
-/// and adjusts the "nice" values of processes based on the configuration.
+/// This function discovers new processes and adjusts the "nice" values of processes
+/// based on the configuration.
+///
+/// Process discovery is event-driven through the kernel proc connector. If the connector
+/// cannot be opened (e.g. without `CAP_NET_ADMIN`) or fails later on, the loop falls back
+/// to periodically polling `/proc`.
 ///
 /// # Arguments
 ///
@@ -21,117 +46,791 @@ use crate::{debug, error, warn};
 /// * `Ok(())` when the event loop completes successfully.
 /// * `Err(anyhow::Error)` if an error occurs during execution.
 pub async fn event_loop() -> Result<()> {
-    let mut previous_pids = HashSet::new();
     let config = config::Config::load_all().unwrap_or_default();
     let matcher = matcher::ProcessMatcher::new(&config);
     let adjuster = adjuster::Adjuster::new(&config);
 
-    loop {
-        let current_pids = match get_running_processes() {
+    match ProcConnector::open() {
+        Ok(connector) => {
+            info!("Using the proc connector for process discovery");
+            let mut monitor = Monitor::new(&matcher, &adjuster);
+            if let Err(e) = monitor.connector_loop(&connector).await {
+                warn!("Proc connector failed: {}", e);
+            }
+        }
+        Err(e) => {
+            warn!("Proc connector unavailable: {:#}", e);
+        }
+    }
+
+    info!("Falling back to polling /proc for process discovery");
+    Monitor::new(&matcher, &adjuster).poll_loop().await
+}
+
+/// The state of a running discovery loop.
+///
+/// The `Monitor` feeds discovered processes into the matcher and adjuster and keeps track
+/// of every known process, so a process that `exec`s into a different program is matched
+/// again. Processes matched by a rule that enforces its settings are re-checked periodically.
+struct Monitor<'a> {
+    matcher: &'a matcher::ProcessMatcher<'a>,
+    adjuster: &'a adjuster::Adjuster<'a>,
+    tracker: ProcessTracker,
+    managed: HashMap<i32, ManagedProcess<'a>>,
+    /// The processes read since the last scan or batch of events.
+    cache: ProcessCache,
+    subtrees: SubtreeTracker<'a>,
+    /// The processes matched by a rule with thread rules, keyed by PID.
+    threaded: HashMap<i32, ThreadedProcess<'a>>,
+}
+
+/// The way a rule applies to a process.
+enum Assignment<'a> {
+    /// The process matches the rule.
+    Matched(&'a ProcessConfig),
+    /// The process belongs to the subtree of a rule with `scope: subtree`.
+    Subtree {
+        /// The PID of the subtree root.
+        root: i32,
+        /// The rule of the subtree.
+        rule: &'a ProcessConfig,
+    },
+}
+
+impl<'a> Assignment<'a> {
+    /// Returns the rule that applies.
+    fn rule(&self) -> &'a ProcessConfig {
+        match self {
+            Assignment::Matched(rule) | Assignment::Subtree { rule, .. } => rule,
+        }
+    }
+}
+
+/// A process whose rule has thread rules.
+struct ThreadedProcess<'a> {
+    /// The rule that matched the process.
+    rule: &'a ProcessConfig,
+    /// Every known thread, keyed by thread ID.
+    threads: HashMap<i32, KnownThread>,
+}
+
+/// A thread of a process with thread rules.
+struct KnownThread {
+    /// The name the thread had when it was last matched.
+    comm: String,
+    /// The identity of the thread, used to make sure the thread ID was not reused. Thread
+    /// identities have no pidfd and hold no file descriptor.
+    identity: ProcessIdentity,
+}
+
+/// A process whose settings are enforced after the initial adjustment.
+struct ManagedProcess<'a> {
+    /// The start time of the process, used to make sure the PID was not reused. No pidfd is
+    /// kept between re-checks, so managing many processes does not use up file descriptors.
+    starttime: u64,
+    /// The rule that matched the process.
+    rule: &'a ProcessConfig,
+    /// When the process is due for its next re-check.
+    next_check: Instant,
+}
+
+impl<'a> Monitor<'a> {
+    /// Creates a new `Monitor` that does not know any process yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `matcher` - The matcher used to find the configuration for a process.
+    /// * `adjuster` - The adjuster applying the configuration to matched processes.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `Monitor`.
+    fn new(matcher: &'a matcher::ProcessMatcher<'a>, adjuster: &'a adjuster::Adjuster<'a>) -> Self {
+        Monitor {
+            matcher,
+            adjuster,
+            tracker: ProcessTracker::new(),
+            managed: HashMap::new(),
+            cache: ProcessCache::new(),
+            subtrees: SubtreeTracker::new(),
+            threaded: HashMap::new(),
+        }
+    }
+
+    /// Discovers processes from proc connector events.
+    /// All processes that already exist are handled once up front, afterwards every fork, exec,
+    /// UID or comm change of a process is fed into the matcher and adjuster as it happens.
+    /// New and renamed threads of processes with thread rules are matched as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `connector` - The subscribed proc connector.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(anyhow::Error)` if receiving events from the connector fails.
+    async fn connector_loop(&mut self, connector: &ProcConnector) -> Result<()> {
+        self.scan_all_processes();
+        let mut ticker = interval(ENFORCE_TICK);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let events = tokio::select! {
+                result = connector.recv() => match result {
+                    Ok(events) => events,
+                    Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                        warn!("Proc connector dropped events, rescanning /proc");
+                        self.scan_all_processes();
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+                _ = ticker.tick() => {
+                    self.enforce_managed();
+                    continue;
+                }
+            };
+
+            self.cache.clear();
+            for event in events {
+                self.handle_event(event);
+            }
+        }
+    }
+
+    /// Discovers processes by periodically polling `/proc`.
+    /// Every 3 seconds all running processes are compared with the previous state. New
+    /// processes and processes that turned into a different program are fed into the
+    /// matcher and adjuster, and the threads of processes with thread rules are listed
+    /// again. Managed processes are re-checked in between scans.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` when the loop completes successfully.
+    /// * `Err(anyhow::Error)` if an error occurs during execution.
+    async fn poll_loop(&mut self) -> Result<()> {
+        let mut ticker = interval(ENFORCE_TICK);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut next_scan = Instant::now();
+
+        loop {
+            ticker.tick().await;
+            if Instant::now() >= next_scan {
+                self.scan_all_processes();
+                next_scan = Instant::now() + POLL_INTERVAL;
+            }
+            self.enforce_managed();
+        }
+    }
+
+    /// Re-checks every managed process that is due according to its rule's `enforce` policy
+    /// and restores its settings if they drifted. Processes whose PID now belongs to a
+    /// different process are no longer managed.
+    fn enforce_managed(&mut self) {
+        let now = Instant::now();
+        let mut gone = Vec::new();
+
+        for (pid, managed) in self.managed.iter_mut() {
+            if now < managed.next_check {
+                continue;
+            }
+            let Some(identity) = ProcessIdentity::open(*pid, managed.starttime) else {
+                gone.push(*pid);
+                continue;
+            };
+
+            self.adjuster.enforce(&identity, managed.rule);
+            if let Some(threaded) = self.threaded.get(pid) {
+                for known in threaded.threads.values() {
+                    if let Some(thread) = managed.rule.thread_rule(&known.comm) {
+                        self.adjuster
+                            .enforce_thread(&known.identity, managed.rule, thread);
+                    }
+                }
+            }
+            managed.next_check = next_check(now, managed.rule.enforce);
+        }
+
+        for pid in gone {
+            trace!("Managed process {} is gone", pid);
+            self.managed.remove(&pid);
+        }
+    }
+
+    /// Observes every process currently listed in `/proc` and handles those that are new
+    /// or `exec`ed since the last scan, then looks for new threads of processes with thread
+    /// rules. Processes that are gone are forgotten.
+    fn scan_all_processes(&mut self) {
+        let pids = match get_running_processes() {
             Ok(pids) => pids,
             Err(e) => {
                 error!("Error fetching processes: {}", e);
+                return;
+            }
+        };
+
+        self.tracker.retain(&pids);
+        self.managed.retain(|pid, _| pids.contains(pid));
+        self.subtrees.retain(&pids);
+        self.threaded.retain(|pid, _| pids.contains(pid));
+        self.matcher.retain(&pids);
+        self.cache.clear();
+
+        // Parents start before their children, so handling processes in the order they
+        // started lets new children join the subtree of a parent that is new as well.
+        let mut processes: Vec<(u64, i32)> = pids
+            .into_iter()
+            .filter_map(|pid| Some((get_starttime_for_pid(pid)?, pid)))
+            .collect();
+        processes.sort_unstable();
+
+        for (starttime, pid) in processes {
+            let Some(fingerprint) = get_fingerprint_for_pid(pid) else {
                 continue;
+            };
+            let change = self.tracker.observe(pid, starttime, fingerprint);
+            self.handle_change(pid, change);
+        }
+
+        let threaded: Vec<i32> = self.threaded.keys().copied().collect();
+        for pid in threaded {
+            self.scan_threads(pid);
+        }
+    }
+
+    /// Updates the known processes from a proc connector event and handles the affected
+    /// process if it is new, `exec`ed, or changed its owner or name. Threads that are created
+    /// or renamed in a process with thread rules are handled as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event received from the proc connector.
+    fn handle_event(&mut self, event: ProcEvent) {
+        trace!("Received process event: {:?}", event);
+        match event {
+            ProcEvent::Fork {
+                child_pid,
+                child_tgid,
+                ..
+            } if child_pid == child_tgid => {
+                if let Some(starttime) = get_starttime_for_pid(child_tgid) {
+                    let fingerprint = get_fingerprint_for_pid(child_tgid);
+                    let change = self.tracker.fork(child_tgid, starttime, fingerprint);
+                    self.handle_change(child_tgid, change);
+                }
+            }
+            ProcEvent::Exec { tgid, .. } => {
+                if let Some(starttime) = get_starttime_for_pid(tgid) {
+                    let fingerprint = get_fingerprint_for_pid(tgid);
+                    let change = self.tracker.exec(tgid, starttime, fingerprint);
+                    self.handle_change(tgid, change);
+                }
+            }
+            ProcEvent::Fork {
+                child_pid,
+                child_tgid,
+                ..
+            } => {
+                if let Some(identity) = get_thread_for_pid(child_tgid, child_pid) {
+                    self.handle_thread(child_tgid, identity);
+                }
+            }
+            ProcEvent::Uid { pid, tgid, .. } | ProcEvent::Comm { pid, tgid, .. } if pid == tgid => {
+                self.handle_attribute_change(tgid);
+            }
+            ProcEvent::Comm { pid, tgid, .. } => {
+                if let Some(identity) = get_thread_for_pid(tgid, pid) {
+                    self.handle_thread(tgid, identity);
+                }
+            }
+            ProcEvent::Exit { pid, tgid } if pid == tgid => {
+                self.tracker.exit(tgid);
+                self.managed.remove(&tgid);
+                self.subtrees.exit(tgid);
+                self.threaded.remove(&tgid);
+                self.matcher.forget(tgid);
+            }
+            ProcEvent::Exit { pid, tgid } => {
+                if let Some(threaded) = self.threaded.get_mut(&tgid) {
+                    threaded.threads.remove(&pid);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a process according to how it changed since it was last observed.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `change` - The change reported by the process tracker.
+    fn handle_change(&mut self, pid: i32, change: Change) {
+        match change {
+            Change::New => {
+                // A new process may reuse the PID of one that was in a subtree.
+                self.subtrees.exit(pid);
+                self.handle_process(pid);
+            }
+            Change::Exec(generation) => {
+                debug!(
+                    "Process {} turned into a different program (exec generation {}), matching again",
+                    pid, generation
+                );
+                self.handle_process(pid);
             }
+            Change::Unchanged => {}
+        }
+    }
+
+    /// Matches a single process against the configuration and adjusts it if a rule applies.
+    /// If the matching rule enforces its settings, the process becomes managed; otherwise
+    /// any previous management of the PID ends.
+    ///
+    /// A process that matches no rule itself but belongs to the subtree of a rule with
+    /// `scope: subtree` is adjusted according to that rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    fn handle_process(&mut self, pid: i32) {
+        self.managed.remove(&pid);
+        self.threaded.remove(&pid);
+
+        let Some(info) = self.read_process(pid) else {
+            return;
         };
+        if let Some(assignment) = self.find_rule(&info) {
+            self.assign(&info, assignment);
+        }
+    }
 
-        let added = current_pids.difference(&previous_pids).collect::<Vec<_>>();
-
-        for pid in added {
-            if let Some(command) = get_command_for_pid(pid) {
-                if let Some(owner) = get_owner_for_pid(pid) {
-                    if let Some(process_config) = matcher.match_command(&command, &owner) {
-                        debug!(
-                            "Process {} with command '{}' and owner '{}' matches config",
-                            pid, command, owner
-                        );
-                        if let Ok(pid_int) = pid.parse::<i32>() {
-                            adjuster.check_and_adjust_nice_value(pid_int, process_config);
-                        }
-                    }
-                } else {
-                    warn!("Failed to get owner for PID {}", pid);
+    /// Matches a process again after its owner or name changed. If the same rule still
+    /// applies in the same way, the process keeps its state, including its enforce timer and
+    /// known threads; otherwise it is handled like a new process.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    fn handle_attribute_change(&mut self, pid: i32) {
+        let Some(info) = self.read_process(pid) else {
+            return;
+        };
+        let assignment = self.find_rule(&info);
+        if let Some(assignment) = &assignment {
+            if self.is_assigned(pid, assignment) {
+                trace!(
+                    "Process {} still matches rule '{}', keeping its state",
+                    pid,
+                    assignment.rule().name
+                );
+                return;
+            }
+        }
+
+        self.managed.remove(&pid);
+        self.threaded.remove(&pid);
+        if let Some(assignment) = assignment {
+            self.assign(&info, assignment);
+        }
+    }
+
+    /// Reads a process afresh, bypassing the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Rc<ProcessInfo>)` with the process.
+    /// * `None` if it could not be read, which is logged.
+    fn read_process(&mut self, pid: i32) -> Option<Rc<ProcessInfo>> {
+        self.cache.invalidate(pid);
+        match self.cache.get(pid) {
+            Ok(info) => Some(info),
+            Err(err) => {
+                warn!("{}", err);
+                None
+            }
+        }
+    }
+
+    /// Finds the rule that applies to a process, either because it matches the rule or
+    /// because it belongs to the subtree of a rule with `scope: subtree`. Members of a
+    /// subtree must run as the owner of its rule, so a user's rule does not follow a
+    /// descendant that changed to another user, e.g. through `sudo`.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The process.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Assignment)` describing how the rule applies.
+    /// * `None` if no rule applies.
+    fn find_rule(&mut self, info: &ProcessInfo) -> Option<Assignment<'a>> {
+        if let Some(rule) = self.matcher.match_process(info, &self.cache) {
+            return Some(Assignment::Matched(rule));
+        }
+
+        let (root, rule) = self
+            .subtrees
+            .subtree_of(info.pid)
+            .or_else(|| self.subtrees.join(info.pid, info.ppid?))
+            .filter(|(_, rule)| rule.kernel_thread == info.kernel_thread)?;
+        if !self.matcher.is_owned(info, rule) {
+            debug!(
+                "Not applying rule '{}' to PID {} in the subtree of {}, it runs as another owner",
+                rule.name, info.pid, root
+            );
+            return None;
+        }
+        self.matcher
+            .is_allowed(info, rule)
+            .then_some(Assignment::Subtree { root, rule })
+    }
+
+    /// Checks whether a process is managed or tracked for threads under the same assignment,
+    /// so its state can be kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `assignment` - The assignment found for the process.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the process has state for the same rule and, for a rule with
+    ///   `scope: subtree` matching the process itself, it already is the subtree root.
+    /// * `false` otherwise.
+    fn is_assigned(&self, pid: i32, assignment: &Assignment<'a>) -> bool {
+        let rule = assignment.rule();
+        let current = self
+            .managed
+            .get(&pid)
+            .map(|managed| managed.rule)
+            .or_else(|| self.threaded.get(&pid).map(|threaded| threaded.rule));
+        let is_root = self.subtrees.subtree_of(pid).map(|(root, _)| root) == Some(pid);
+
+        current.is_some_and(|current| std::ptr::eq(current, rule))
+            && match assignment {
+                Assignment::Matched(rule) => rule.scope != Scope::Subtree || is_root,
+                Assignment::Subtree { .. } => !is_root,
+            }
+    }
+
+    /// Applies the rule found for a process and starts a subtree if the rule matched the
+    /// process itself and has `scope: subtree`.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The process.
+    /// * `assignment` - How the rule applies to the process.
+    fn assign(&mut self, info: &ProcessInfo, assignment: Assignment<'a>) {
+        let pid = info.pid;
+        match assignment {
+            Assignment::Matched(rule) => {
+                debug!(
+                    "Process {} with command '{}' and owner '{}' matches config",
+                    pid,
+                    info.command,
+                    info.owner()
+                );
+                self.apply_rule(info, rule);
+                if rule.scope == Scope::Subtree {
+                    self.subtrees.add_root(pid, rule);
+                    self.adopt_descendants(pid);
                 }
-            } else {
-                warn!("Failed to get command string for PID {}", pid);
+            }
+            Assignment::Subtree { root, rule } => {
+                debug!(
+                    "Process {} with command '{}' belongs to the subtree of PID {} matched by '{}'",
+                    pid, info.command, root, rule.name
+                );
+                self.apply_rule(info, rule);
             }
         }
+    }
 
-        previous_pids = current_pids;
+    /// Adjusts a process and its threads according to a rule and starts managing it if the
+    /// rule enforces its settings. Nothing is done if the PID no longer belongs to the
+    /// process that was matched.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The process as it was matched.
+    /// * `rule` - The rule that applies to the process.
+    fn apply_rule(&mut self, info: &ProcessInfo, rule: &'a ProcessConfig) {
+        let pid = info.pid;
+        let Some(identity) = ProcessIdentity::open(pid, info.starttime) else {
+            debug!(
+                "Process {} exited or its PID was reused before rule '{}' could be applied",
+                pid, rule.name
+            );
+            return;
+        };
 
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        self.adjuster.check_and_adjust(&identity, rule);
+        self.manage(&identity, rule);
+        if !rule.threads.is_empty() {
+            self.threaded.insert(
+                pid,
+                ThreadedProcess {
+                    rule,
+                    threads: HashMap::new(),
+                },
+            );
+            self.scan_threads(pid);
+        }
     }
-}
 
-/// Retrieves the PIDs of currently running processes from the `/proc` directory.
-/// This function reads the `/proc` directory and filters entries that represent PIDs.
-///
-/// # Returns
-///
-/// * `Ok(HashSet<String>)` containing the PIDs of the currently running processes.
-/// * `Err(anyhow::Error)` if there's an error reading the directory.
-fn get_running_processes() -> Result<HashSet<String>> {
-    let mut pids = HashSet::new();
+    /// Lists the threads of a process with thread rules, handles the new and renamed ones
+    /// and forgets those that exited.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    fn scan_threads(&mut self, pid: i32) {
+        let threads = match get_threads_for_pid(pid) {
+            Ok(threads) => threads,
+            Err(e) => {
+                debug!("{:#}", e);
+                return;
+            }
+        };
+
+        if let Some(threaded) = self.threaded.get_mut(&pid) {
+            threaded
+                .threads
+                .retain(|tid, _| threads.iter().any(|thread| thread.pid == *tid));
+        }
+        for identity in threads {
+            self.handle_thread(pid, identity);
+        }
+    }
+
+    /// Matches a thread of a process with thread rules by its name and adjusts it if a
+    /// thread rule applies. Threads whose name did not change since they were last matched
+    /// are skipped, as is the main thread, which follows the rule of the process.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `identity` - The identity of the thread, captured when the threads were listed.
+    fn handle_thread(&mut self, pid: i32, identity: ProcessIdentity) {
+        let tid = identity.pid;
+        if tid == pid {
+            return;
+        }
+        let Some(threaded) = self.threaded.get_mut(&pid) else {
+            return;
+        };
+        let Some(comm) = get_comm_for_thread(pid, tid) else {
+            return;
+        };
+        let unchanged = threaded.threads.get(&tid).is_some_and(|known| {
+            known.comm == comm && known.identity.starttime == identity.starttime
+        });
+        if unchanged {
+            return;
+        }
+
+        let rule = threaded.rule;
+        if let Some(thread) = rule.thread_rule(&comm) {
+            debug!(
+                "Thread {} '{}' of PID {} matches thread rule '{}' of rule '{}'",
+                tid, comm, pid, thread.comm, rule.name
+            );
+            self.adjuster
+                .check_and_adjust_thread(&identity, rule, thread);
+        }
+        threaded.threads.insert(tid, KnownThread { comm, identity });
+    }
+
+    /// Adds the current descendants of a new subtree root to its subtree and handles them.
+    /// Future descendants join when they are discovered.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The PID of the subtree root.
+    fn adopt_descendants(&mut self, root: i32) {
+        let pids = match get_running_processes() {
+            Ok(pids) => pids,
+            Err(e) => {
+                error!("Error fetching processes: {}", e);
+                return;
+            }
+        };
+
+        let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+        for pid in pids {
+            if let Some(ppid) = get_ppid_for_pid(pid) {
+                children.entry(ppid).or_default().push(pid);
+            }
+        }
+
+        let mut descendants = Vec::new();
+        let mut queue = vec![root];
+        while let Some(parent) = queue.pop() {
+            for &child in children.get(&parent).into_iter().flatten() {
+                self.subtrees.join(child, parent);
+                descendants.push(child);
+                queue.push(child);
+            }
+        }
 
-    for entry in fs::read_dir("/proc")?.filter_map(Result::ok) {
-        if let Some(pid_str) = entry.file_name().to_str() {
-            if pid_str.chars().all(char::is_numeric) {
-                pids.insert(pid_str.to_string());
+        for pid in descendants {
+            // Skip processes that moved to a nested subtree while handling an ancestor.
+            if self.subtrees.subtree_of(pid).map(|(root, _)| root) == Some(root) {
+                self.handle_process(pid);
             }
         }
     }
 
-    Ok(pids)
+    /// Starts managing a process if its rule enforces its settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - The identity of the process.
+    /// * `rule` - The rule that matched the process.
+    fn manage(&mut self, identity: &ProcessIdentity, rule: &'a ProcessConfig) {
+        if rule.enforce == EnforcePolicy::Once {
+            return;
+        }
+        let pid = identity.pid;
+
+        trace!(
+            "Managing process {} with enforce policy {:?}",
+            pid,
+            rule.enforce
+        );
+        self.managed.insert(
+            pid,
+            ManagedProcess {
+                starttime: identity.starttime,
+                rule,
+                next_check: next_check(Instant::now(), rule.enforce),
+            },
+        );
+    }
 }
 
-/// Retrieves the command line of a process based on its PID from `/proc/{pid}/cmdline`.
-/// This function reads the `cmdline` file of a given process and returns the command line as a string.
+/// Returns the time between two re-checks for an enforce policy.
 ///
-/// # Parameters
+/// # Arguments
 ///
-/// * `pid` - The PID of the process whose command line is to be fetched.
+/// * `policy` - The enforce policy of a rule.
 ///
 /// # Returns
 ///
-/// * `Some<String>` containing the command line if successful.
-/// * `None` if there's an error or the command line could not be read.
-fn get_command_for_pid(pid: &str) -> Option<String> {
-    let cmdline_path = format!("/proc/{}/cmdline", pid);
-    fs::read_to_string(cmdline_path)
-        .ok()
-        .map(|cmd| cmd.replace("\0", " "))
+/// * The interval after which a managed process is checked again. `always` is checked on
+///   every enforcement tick.
+fn enforce_interval(policy: EnforcePolicy) -> Duration {
+    match policy {
+        EnforcePolicy::Interval(interval) => interval,
+        EnforcePolicy::Once | EnforcePolicy::Always => ENFORCE_TICK,
+    }
 }
 
-/// Retrieves the owner (username) of a process based on its PID.
-/// This function uses `/proc/[pid]/status` to fetch the UID of the process and maps it to a username.
+/// Returns when a managed process is due for its next re-check.
 ///
-/// # Parameters
+/// # Arguments
 ///
-/// * `pid` - The PID of the process whose owner is to be fetched.
+/// * `now` - The current time.
+/// * `policy` - The enforce policy of a rule.
 ///
 /// # Returns
 ///
-/// * `Some<String>` containing the username of the process owner.
-/// * `None` if the username could not be resolved.
-fn get_owner_for_pid(pid: &str) -> Option<String> {
-    let status_path = format!("/proc/{}/status", pid);
-    let mut file = fs::File::open(status_path).ok()?;
+/// * The time of the next re-check, at most `MAX_ENFORCE_INTERVAL` from now.
+fn next_check(now: Instant, policy: EnforcePolicy) -> Instant {
+    now + enforce_interval(policy).min(MAX_ENFORCE_INTERVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
 
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).ok()?;
+    /// Tests that huge enforce intervals are capped instead of overflowing the deadline.
+    #[test]
+    fn test_next_check_caps_interval() {
+        let now = Instant::now();
+        let huge = EnforcePolicy::Interval(Duration::from_secs(u64::MAX));
+        assert_eq!(next_check(now, huge), now + MAX_ENFORCE_INTERVAL);
+        assert_eq!(next_check(now, EnforcePolicy::Always), now + ENFORCE_TICK);
+    }
 
-    let uid_line = contents.lines().find(|line| line.starts_with("Uid:"))?;
-    let uid_str = uid_line.split_whitespace().nth(1)?;
+    /// Tests that a process whose name or owner changed without affecting its match keeps
+    /// its enforce timer, instead of being managed from scratch.
+    #[test]
+    fn test_attribute_change_keeps_state() {
+        let yaml = r#"
+process:
+- { name: sleeper, bin: sleep, nice: 0, enforce: always, matcher: { type: simple, match_string: "sleep 4243" } }
+"#;
+        let mut config: config::Config = serde_yaml::from_str(yaml).unwrap();
+        config.process[0].matcher.compile().unwrap();
+        let matcher = matcher::ProcessMatcher::new(&config);
+        let adjuster = adjuster::Adjuster::new(&config);
+        let mut monitor = Monitor::new(&matcher, &adjuster);
 
-    if let Ok(uid) = uid_str.parse::<u32>() {
-        if let Ok(user) = User::from_uid(Uid::from_raw(uid)) {
-            if let Some(u) = user {
-                return Some(u.name.to_string());
-            } else {
-                return Some("root".to_string());
-            }
+        let mut child = Command::new("sleep")
+            .arg("4243")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        // The child runs the test binary until it has `exec`ed.
+        while get_fingerprint_for_pid(pid).is_none_or(|f| !f.command.starts_with("sleep")) {
+            std::thread::sleep(Duration::from_millis(1));
         }
+        monitor.handle_process(pid);
+        let next_check = monitor.managed[&pid].next_check;
+
+        monitor.handle_attribute_change(pid);
+        assert_eq!(monitor.managed[&pid].next_check, next_check);
+
+        monitor.handle_process(pid);
+        assert_ne!(monitor.managed[&pid].next_check, next_check);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
     }
 
-    None
+    /// Tests that a descendant in a subtree is only adjusted if it runs as the owner of the
+    /// subtree's rule.
+    #[test]
+    fn test_subtree_checks_owner() {
+        let uid = nix::unistd::getuid().as_raw();
+        for (owner, adjusted) in [(uid, true), (uid.wrapping_add(1), false)] {
+            let yaml = format!(
+                "process:\n- {{ name: tree, bin: none, nice: 0, enforce: always, scope: subtree, owner: '{}', matcher: {{ type: comm }} }}\n",
+                owner
+            );
+            let mut config: config::Config = serde_yaml::from_str(&yaml).unwrap();
+            config.process[0].owner.as_mut().unwrap().resolve();
+            let matcher = matcher::ProcessMatcher::new(&config);
+            let adjuster = adjuster::Adjuster::new(&config);
+            let mut monitor = Monitor::new(&matcher, &adjuster);
+            monitor
+                .subtrees
+                .add_root(std::process::id() as i32, &config.process[0]);
+
+            let mut child = Command::new("sleep")
+                .arg("4244")
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap();
+            let pid = child.id() as i32;
+            while get_fingerprint_for_pid(pid).is_none_or(|f| !f.command.starts_with("sleep")) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            monitor.handle_process(pid);
+            assert_eq!(monitor.managed.contains_key(&pid), adjusted);
+
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+    }
 }
@@ -0,0 +1,874 @@
+use anyhow::{Context, Result};
+use nix::libc;
+use nix::unistd::{Uid, User};
+use procfs::process::{Process, StatFlags};
+use std::cell::{OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::cgroup::get_cgroup_for_pid;
+use crate::container::{get_container_for_pid, lookup_container_name, ContainerInfo};
+use crate::debug;
+
+/// Identifies the program a process is currently running.
+///
+/// Two fingerprints of the same PID differ when the process `exec`ed into another program
+/// (or rewrote its command line) between two observations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// The resolved executable path, if it could be read.
+    pub exe: Option<PathBuf>,
+    /// The command line with arguments joined by spaces.
+    pub command: String,
+}
+
+/// The user and group IDs of a process, from `/proc/{pid}/status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// The real UID.
+    pub uid: u32,
+    /// The effective UID.
+    pub euid: u32,
+    /// The real GID.
+    pub gid: u32,
+    /// The effective GID.
+    pub egid: u32,
+    /// The supplementary GIDs.
+    pub groups: Vec<u32>,
+}
+
+/// Pins down the process a PID referred to when it was discovered.
+///
+/// PIDs are reused as soon as a process exits, so a process may exit between being matched
+/// and being adjusted, and its PID may then belong to an unrelated process. The identity
+/// keeps the start time of the process and, where the kernel supports it, a pidfd, so every
+/// change can be checked to still reach the matched process. Since a pidfd is a file
+/// descriptor, identities of processes are only kept for the duration of an adjustment.
+#[derive(Debug)]
+pub struct ProcessIdentity {
+    /// The PID of the process, or the thread ID of a thread.
+    pub pid: i32,
+    /// The start time of the process in clock ticks since boot.
+    pub starttime: u64,
+    /// A pidfd referring to the process, `None` for threads or if it could not be opened.
+    pidfd: Option<OwnedFd>,
+}
+
+impl ProcessIdentity {
+    /// Opens the identity of a process that was discovered with the given start time.
+    ///
+    /// # Parameters
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `starttime` - The start time the process had when it was discovered.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(ProcessIdentity)` if the PID still belongs to the discovered process.
+    /// * `None` if the process exited, or its PID now belongs to a different process.
+    pub fn open(pid: i32, starttime: u64) -> Option<Self> {
+        let pidfd = open_pidfd(pid);
+        // The start time is compared after opening the pidfd, so the pidfd is known to refer
+        // to the discovered process and not to one that reused its PID.
+        (get_starttime_for_pid(pid)? == starttime).then_some(ProcessIdentity {
+            pid,
+            starttime,
+            pidfd,
+        })
+    }
+
+    /// Creates the identity of a thread that was listed with the given start time. Only the
+    /// main thread of a process can have a pidfd, so threads are identified by their start
+    /// time alone.
+    ///
+    /// # Parameters
+    ///
+    /// * `tid` - The thread ID of the thread.
+    /// * `starttime` - The start time the thread had when it was listed.
+    ///
+    /// # Returns
+    ///
+    /// * A new `ProcessIdentity` for the thread.
+    pub fn thread(tid: i32, starttime: u64) -> Self {
+        ProcessIdentity {
+            pid: tid,
+            starttime,
+            pidfd: None,
+        }
+    }
+
+    /// Checks whether the PID still belongs to the identified process.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the process is still running (or a zombie) under its PID.
+    /// * `false` if it exited, or its PID was reused by a different process.
+    pub fn is_current(&self) -> bool {
+        match &self.pidfd {
+            // Sending signal 0 only checks whether the process the pidfd refers to still
+            // exists. EPERM means it exists but may not be signalled by reniced.
+            Some(pidfd) => {
+                let result = unsafe {
+                    libc::syscall(
+                        libc::SYS_pidfd_send_signal,
+                        pidfd.as_raw_fd(),
+                        0,
+                        ptr::null::<libc::siginfo_t>(),
+                        0,
+                    )
+                };
+                result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+            }
+            None => get_starttime_for_pid(self.pid) == Some(self.starttime),
+        }
+    }
+}
+
+/// Opens a pidfd referring to a process with `pidfd_open(2)`.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process.
+///
+/// # Returns
+///
+/// * `Some(OwnedFd)` with the pidfd.
+/// * `None` if the process is gone, the kernel does not support pidfds (before Linux 5.3)
+///   or no file descriptor is left. The start time alone identifies the process then.
+fn open_pidfd(pid: i32) -> Option<OwnedFd> {
+    let raw = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if raw < 0 {
+        debug!(
+            "Could not open a pidfd for PID {}: {}",
+            pid,
+            io::Error::last_os_error()
+        );
+        return None;
+    }
+    Some(unsafe { OwnedFd::from_raw_fd(raw as libc::c_int) })
+}
+
+/// The attributes of a process that rules are matched against.
+///
+/// The command line and credentials are always needed and read up front. Everything else is
+/// only read from `/proc` the first time a matcher asks for it.
+#[derive(Debug)]
+pub struct ProcessInfo {
+    /// The PID of the process.
+    pub pid: i32,
+    /// The command line with arguments joined by spaces.
+    pub command: String,
+    /// The command line as separate arguments, starting with the program name.
+    pub argv: Vec<String>,
+    /// The user and group IDs of the process.
+    pub credentials: Credentials,
+    /// The PID of the parent process, `None` if it is unknown or the process has no parent.
+    pub ppid: Option<i32>,
+    /// The start time of the process, read before any other attribute.
+    pub starttime: u64,
+    /// Whether the process is a kernel thread. Kernel threads have no command line.
+    pub kernel_thread: bool,
+    owner: OnceCell<String>,
+    comm: OnceCell<Option<String>>,
+    exe: OnceCell<Option<PathBuf>>,
+    cgroup: OnceCell<Option<String>>,
+    container: OnceCell<Option<ContainerInfo>>,
+    container_name: OnceCell<Option<String>>,
+    environ: OnceCell<Option<HashMap<String, String>>>,
+}
+
+impl ProcessInfo {
+    /// Reads the start time, command line, credentials and parent of a process, and whether
+    /// it is a kernel thread.
+    ///
+    /// The start time is read first: if the process exits and its PID is reused while the
+    /// other attributes are read, the start time no longer matches when the process is
+    /// adjusted, and the adjustment is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ProcessInfo)` for the process.
+    /// * `Err(anyhow::Error)` if the start time, command line or credentials could not be read.
+    pub fn read(pid: i32) -> Result<Self> {
+        let stat = Process::new(pid)
+            .and_then(|process| process.stat())
+            .with_context(|| format!("Failed to read stat of PID {}", pid))?;
+        let cmdline = get_cmdline_for_pid(pid)
+            .with_context(|| format!("Failed to get command string for PID {}", pid))?;
+        let status = fs::read_to_string(format!("/proc/{}/status", pid))
+            .with_context(|| format!("Failed to read status of PID {}", pid))?;
+        let credentials = parse_credentials(&status)
+            .with_context(|| format!("Failed to get credentials for PID {}", pid))?;
+        let mut info = ProcessInfo::new(pid, parse_cmdline(&cmdline), credentials);
+        info.ppid = parse_ppid(&status);
+        info.starttime = stat.starttime;
+        // The raw flags are checked, `stat.flags()` fails on bits procfs does not know.
+        info.kernel_thread = stat.flags & StatFlags::PF_KTHREAD.bits() != 0;
+        Ok(info)
+    }
+
+    /// Creates a `ProcessInfo` from known attributes. Other attributes are read from `/proc`
+    /// on demand.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `argv` - The command line arguments, starting with the program name.
+    /// * `credentials` - The user and group IDs of the process.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `ProcessInfo`.
+    pub fn new(pid: i32, argv: Vec<String>, credentials: Credentials) -> Self {
+        ProcessInfo {
+            pid,
+            command: argv.iter().map(|arg| format!("{} ", arg)).collect(),
+            argv,
+            credentials,
+            ppid: None,
+            starttime: 0,
+            kernel_thread: false,
+            owner: OnceCell::new(),
+            comm: OnceCell::new(),
+            exe: OnceCell::new(),
+            cgroup: OnceCell::new(),
+            container: OnceCell::new(),
+            container_name: OnceCell::new(),
+            environ: OnceCell::new(),
+        }
+    }
+
+    /// Returns the name of the user owning the process, for log messages.
+    ///
+    /// # Returns
+    ///
+    /// * The name of the real UID, or the UID itself if it has no name (e.g. a UID mapped
+    ///   into a container).
+    pub fn owner(&self) -> &str {
+        self.owner.get_or_init(|| {
+            get_username_for_uid(self.credentials.uid)
+                .unwrap_or_else(|| self.credentials.uid.to_string())
+        })
+    }
+
+    /// Returns the command name of the process from `/proc/{pid}/comm`.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&str)` with the command name, as truncated by the kernel.
+    /// * `None` if it could not be read.
+    pub fn comm(&self) -> Option<&str> {
+        self.comm
+            .get_or_init(|| get_comm_for_pid(self.pid))
+            .as_deref()
+    }
+
+    /// Returns the resolved executable path of the process from `/proc/{pid}/exe`.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&Path)` with the executable path.
+    /// * `None` if it could not be read.
+    pub fn exe(&self) -> Option<&Path> {
+        self.exe
+            .get_or_init(|| get_exe_for_pid(self.pid))
+            .as_deref()
+    }
+
+    /// Returns the cgroup v2 path of the process from `/proc/{pid}/cgroup`.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&str)` with the path relative to the cgroup root.
+    /// * `None` if it could not be read.
+    pub fn cgroup(&self) -> Option<&str> {
+        self.cgroup
+            .get_or_init(|| get_cgroup_for_pid(self.pid).ok())
+            .as_deref()
+    }
+
+    /// Returns the container the process runs in, derived from its cgroup path and PID
+    /// namespace.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&ContainerInfo)` if the process runs in a container.
+    /// * `None` if it runs on the host.
+    pub fn container(&self) -> Option<&ContainerInfo> {
+        self.container
+            .get_or_init(|| get_container_for_pid(self.pid, self.cgroup()))
+            .as_ref()
+    }
+
+    /// Returns the name of the container the process runs in, from the runtime's state files.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&str)` with the container name.
+    /// * `None` if the process is not in a container or the name is unknown.
+    pub fn container_name(&self) -> Option<&str> {
+        self.container_name
+            .get_or_init(|| self.container().and_then(lookup_container_name))
+            .as_deref()
+    }
+
+    /// Returns the environment of the process from `/proc/{pid}/environ`.
+    /// Reading the environment of another user's process requires `CAP_SYS_PTRACE`; a
+    /// failure is only logged at debug level, since it is expected for some processes.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(&HashMap)` mapping variable names to values.
+    /// * `None` if the environment could not be read.
+    pub fn environ(&self) -> Option<&HashMap<String, String>> {
+        self.environ
+            .get_or_init(|| match get_environ_for_pid(self.pid) {
+                Ok(environ) => Some(environ),
+                Err(err) => {
+                    debug!("{:#}", err);
+                    None
+                }
+            })
+            .as_ref()
+    }
+}
+
+/// Caches the `ProcessInfo` of every process read during one scan, so walking the parents
+/// of many processes in a deep process tree reads each of them from `/proc` only once.
+#[derive(Debug, Default)]
+pub struct ProcessCache {
+    processes: RefCell<HashMap<i32, Rc<ProcessInfo>>>,
+}
+
+impl ProcessCache {
+    /// Creates an empty `ProcessCache`.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `ProcessCache`.
+    pub fn new() -> Self {
+        ProcessCache::default()
+    }
+
+    /// Returns the cached `ProcessInfo` of a process, reading it on first use.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Rc<ProcessInfo>)` for the process.
+    /// * `Err(anyhow::Error)` if the process could not be read. Failures are not cached.
+    pub fn get(&self, pid: i32) -> Result<Rc<ProcessInfo>> {
+        if let Some(info) = self.processes.borrow().get(&pid) {
+            return Ok(Rc::clone(info));
+        }
+        Ok(self.insert(ProcessInfo::read(pid)?))
+    }
+
+    /// Adds a process to the cache, replacing any previous entry for its PID.
+    ///
+    /// # Arguments
+    ///
+    /// * `info` - The attributes of the process.
+    ///
+    /// # Returns
+    ///
+    /// * The cached `Rc<ProcessInfo>`.
+    pub fn insert(&self, info: ProcessInfo) -> Rc<ProcessInfo> {
+        let info = Rc::new(info);
+        self.processes
+            .borrow_mut()
+            .insert(info.pid, Rc::clone(&info));
+        info
+    }
+
+    /// Drops the cached entry of a process that changed, e.g. after an `exec`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    pub fn invalidate(&self, pid: i32) {
+        self.processes.borrow_mut().remove(&pid);
+    }
+
+    /// Drops all cached entries at the start of a new scan.
+    pub fn clear(&self) {
+        self.processes.borrow_mut().clear();
+    }
+}
+
+/// Retrieves the PIDs of currently running processes from the `/proc` directory.
+/// This function reads the `/proc` directory and filters entries that represent PIDs.
+///
+/// # Returns
+///
+/// * `Ok(HashSet<i32>)` containing the PIDs of the currently running processes.
+/// * `Err(anyhow::Error)` if there's an error reading the directory.
+pub fn get_running_processes() -> Result<HashSet<i32>> {
+    let mut pids = HashSet::new();
+
+    for entry in fs::read_dir("/proc")?.filter_map(Result::ok) {
+        if let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+            pids.insert(pid);
+        }
+    }
+
+    Ok(pids)
+}
+
+/// Retrieves the command line of a process based on its PID from `/proc/{pid}/cmdline`.
+/// This function reads the `cmdline` file of a given process and returns the command line as a string.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose command line is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<String>` containing the command line if successful.
+/// * `None` if there's an error or the command line could not be read.
+pub fn get_command_for_pid(pid: i32) -> Option<String> {
+    get_cmdline_for_pid(pid).map(|cmd| cmd.replace("\0", " "))
+}
+
+/// Reads the raw, NUL separated command line of a process from `/proc/{pid}/cmdline`.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose command line is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<String>` containing the raw command line.
+/// * `None` if the command line could not be read.
+fn get_cmdline_for_pid(pid: i32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/cmdline", pid)).ok()
+}
+
+/// Reads the environment of a process from `/proc/{pid}/environ`.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose environment is to be fetched.
+///
+/// # Returns
+///
+/// * `Ok(HashMap<String, String>)` mapping variable names to values.
+/// * `Err(anyhow::Error)` if the file could not be read, e.g. for lack of permissions.
+pub fn get_environ_for_pid(pid: i32) -> Result<HashMap<String, String>> {
+    let path = format!("/proc/{}/environ", pid);
+    let content = fs::read(&path)
+        .with_context(|| format!("Failed to read environment of PID {} from {}", pid, path))?;
+    Ok(parse_environ(&String::from_utf8_lossy(&content)))
+}
+
+/// Splits the raw contents of `/proc/{pid}/environ` into variables. Entries without a `=`
+/// are skipped.
+///
+/// # Parameters
+///
+/// * `environ` - The NUL separated `NAME=value` entries.
+///
+/// # Returns
+///
+/// * A `HashMap<String, String>` mapping variable names to values.
+fn parse_environ(environ: &str) -> HashMap<String, String> {
+    environ
+        .split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Splits a raw command line into its arguments. Every argument is terminated by a NUL
+/// byte, so arguments containing spaces are kept intact.
+///
+/// # Parameters
+///
+/// * `cmdline` - The raw contents of `/proc/{pid}/cmdline`.
+///
+/// # Returns
+///
+/// * A `Vec<String>` with one entry per argument.
+fn parse_cmdline(cmdline: &str) -> Vec<String> {
+    let cmdline = cmdline.strip_suffix('\0').unwrap_or(cmdline);
+    if cmdline.is_empty() {
+        return Vec::new();
+    }
+    cmdline.split('\0').map(str::to_string).collect()
+}
+
+/// Retrieves the command name of a process from `/proc/{pid}/comm`.
+/// The kernel truncates the name to 15 bytes.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose command name is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<String>` containing the command name without the trailing newline.
+/// * `None` if the file could not be read.
+pub fn get_comm_for_pid(pid: i32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|comm| comm.trim_end_matches('\n').to_string())
+}
+
+/// Retrieves the name of a thread from `/proc/{pid}/task/{tid}/comm`.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process the thread belongs to.
+/// * `tid` - The thread ID.
+///
+/// # Returns
+///
+/// * `Some<String>` containing the thread name without the trailing newline.
+/// * `None` if the thread is gone or the file could not be read.
+pub fn get_comm_for_thread(pid: i32, tid: i32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, tid))
+        .ok()
+        .map(|comm| comm.trim_end_matches('\n').to_string())
+}
+
+/// Lists the thread IDs of a process from `/proc/{pid}/task`. The main thread has the PID
+/// of the process as its thread ID.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process.
+///
+/// # Returns
+///
+/// * `Ok(Vec<i32>)` containing the thread IDs.
+/// * `Err(anyhow::Error)` if the task directory could not be read.
+pub fn get_thread_ids_for_pid(pid: i32) -> Result<Vec<i32>> {
+    let entries = fs::read_dir(format!("/proc/{}/task", pid))
+        .with_context(|| format!("Could not list threads of PID {}", pid))?;
+
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect())
+}
+
+/// Lists the threads of a process from `/proc/{pid}/task` and captures the identity of
+/// each thread as it is listed, so a thread ID that is reused before the thread is adjusted
+/// no longer matches its identity.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ProcessIdentity>)` containing the identities of the threads that were running.
+/// * `Err(anyhow::Error)` if the task directory could not be read.
+pub fn get_threads_for_pid(pid: i32) -> Result<Vec<ProcessIdentity>> {
+    let tasks = Process::new(pid)
+        .and_then(|process| process.tasks())
+        .with_context(|| format!("Could not list threads of PID {}", pid))?;
+
+    Ok(tasks
+        .filter_map(|task| {
+            let task = task.ok()?;
+            Some(ProcessIdentity::thread(
+                task.tid,
+                task.stat().ok()?.starttime,
+            ))
+        })
+        .collect())
+}
+
+/// Captures the identity of a single thread of a process from `/proc/{pid}/task/{tid}`.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process.
+/// * `tid` - The thread ID of the thread.
+///
+/// # Returns
+///
+/// * `Some(ProcessIdentity)` for the thread.
+/// * `None` if the thread is gone or does not belong to the process.
+pub fn get_thread_for_pid(pid: i32, tid: i32) -> Option<ProcessIdentity> {
+    let task = Process::new(pid).ok()?.task_from_tid(tid).ok()?;
+    Some(ProcessIdentity::thread(tid, task.stat().ok()?.starttime))
+}
+
+/// Resolves a UID to a username.
+///
+/// # Parameters
+///
+/// * `uid` - The UID to resolve.
+///
+/// # Returns
+///
+/// * `Some<String>` containing the username.
+/// * `None` if no user has this UID.
+pub fn get_username_for_uid(uid: u32) -> Option<String> {
+    User::from_uid(Uid::from_raw(uid))
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+}
+
+/// Parses the real and effective UIDs and GIDs and the supplementary groups from the
+/// contents of `/proc/{pid}/status`.
+///
+/// # Parameters
+///
+/// * `status` - The contents of the status file.
+///
+/// # Returns
+///
+/// * `Some<Credentials>` containing the IDs of the process.
+/// * `None` if the `Uid:` or `Gid:` line is missing or malformed.
+fn parse_credentials(status: &str) -> Option<Credentials> {
+    let ids = |key: &str| -> Option<Vec<u32>> {
+        let line = status.lines().find_map(|line| line.strip_prefix(key))?;
+        line.split_whitespace().map(|id| id.parse().ok()).collect()
+    };
+    let uids = ids("Uid:")?;
+    let gids = ids("Gid:")?;
+
+    Some(Credentials {
+        uid: *uids.first()?,
+        euid: *uids.get(1)?,
+        gid: *gids.first()?,
+        egid: *gids.get(1)?,
+        groups: ids("Groups:").unwrap_or_default(),
+    })
+}
+
+/// Retrieves the PID of the parent of a process from `/proc/{pid}/status`.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose parent is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<i32>` containing the parent PID.
+/// * `None` if the status file could not be read or the process has no parent (PPid 0).
+pub fn get_ppid_for_pid(pid: i32) -> Option<i32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    parse_ppid(&status)
+}
+
+/// Parses the parent PID from the contents of `/proc/{pid}/status`.
+///
+/// # Parameters
+///
+/// * `status` - The contents of the status file.
+///
+/// # Returns
+///
+/// * `Some<i32>` containing the parent PID.
+/// * `None` if the line is missing or the process has no parent (PPid 0).
+fn parse_ppid(status: &str) -> Option<i32> {
+    let ppid_line = status.lines().find(|line| line.starts_with("PPid:"))?;
+    let ppid: i32 = ppid_line.split_whitespace().nth(1)?.parse().ok()?;
+    (ppid > 0).then_some(ppid)
+}
+
+/// Retrieves the start time of a process from `/proc/{pid}/stat`.
+/// The start time is measured in clock ticks since boot and, together with the PID,
+/// uniquely identifies a process even when PIDs are reused.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose start time is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<u64>` containing the start time of the process.
+/// * `None` if the process is gone or its stat file could not be parsed.
+pub fn get_starttime_for_pid(pid: i32) -> Option<u64> {
+    Process::new(pid)
+        .ok()?
+        .stat()
+        .ok()
+        .map(|stat| stat.starttime)
+}
+
+/// Retrieves the resolved executable path of a process from `/proc/{pid}/exe`.
+/// If the binary was replaced or removed while the process is running, the kernel appends
+/// ` (deleted)` to the link; the suffix is removed so the original path is returned.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose executable is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<PathBuf>` containing the executable path.
+/// * `None` if the link could not be read (e.g. kernel threads or missing permissions).
+pub fn get_exe_for_pid(pid: i32) -> Option<PathBuf> {
+    fs::read_link(format!("/proc/{}/exe", pid))
+        .ok()
+        .map(strip_deleted_suffix)
+}
+
+/// Removes the ` (deleted)` suffix the kernel appends to the `exe` link of a process whose
+/// binary no longer exists at its path.
+///
+/// # Parameters
+///
+/// * `path` - The target of the `exe` link.
+///
+/// # Returns
+///
+/// * The path without the suffix.
+fn strip_deleted_suffix(path: PathBuf) -> PathBuf {
+    match path
+        .to_str()
+        .and_then(|path| path.strip_suffix(" (deleted)"))
+    {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    }
+}
+
+/// Retrieves the fingerprint of the program a process is currently running.
+///
+/// # Parameters
+///
+/// * `pid` - The PID of the process whose fingerprint is to be fetched.
+///
+/// # Returns
+///
+/// * `Some<Fingerprint>` containing the executable path and command line.
+/// * `None` if the command line could not be read.
+pub fn get_fingerprint_for_pid(pid: i32) -> Option<Fingerprint> {
+    Some(Fingerprint {
+        command: get_command_for_pid(pid)?,
+        exe: get_exe_for_pid(pid),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the suffix of an upgraded binary is removed and other paths are kept.
+    #[test]
+    fn test_strip_deleted_suffix() {
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/usr/bin/foo (deleted)")),
+            PathBuf::from("/usr/bin/foo")
+        );
+        assert_eq!(
+            strip_deleted_suffix(PathBuf::from("/usr/bin/foo")),
+            PathBuf::from("/usr/bin/foo")
+        );
+    }
+
+    /// Tests that arguments containing spaces are kept apart from their neighbours.
+    #[test]
+    fn test_parse_cmdline() {
+        assert_eq!(parse_cmdline("a b\0c\0"), vec!["a b", "c"]);
+        assert_eq!(parse_cmdline("a\0b c\0"), vec!["a", "b c"]);
+        assert_eq!(parse_cmdline("a\0\0c\0"), vec!["a", "", "c"]);
+        assert!(parse_cmdline("").is_empty());
+    }
+
+    /// Tests that the parent of the current process is read, and that the cache returns the
+    /// same entry until it is invalidated.
+    #[test]
+    fn test_process_cache() {
+        let pid = std::process::id() as i32;
+        let cache = ProcessCache::new();
+        let info = cache.get(pid).unwrap();
+        assert_eq!(info.ppid, Some(nix::unistd::getppid().as_raw()));
+        assert!(Rc::ptr_eq(&info, &cache.get(pid).unwrap()));
+
+        cache.invalidate(pid);
+        assert!(!Rc::ptr_eq(&info, &cache.get(pid).unwrap()));
+    }
+
+    /// Tests that environment entries are split at the first `=` and malformed entries are
+    /// skipped.
+    #[test]
+    fn test_parse_environ() {
+        let environ = parse_environ("JOB_CLASS=batch\0OPTS=a=b\0EMPTY=\0garbage\0");
+        assert_eq!(environ.get("JOB_CLASS").map(String::as_str), Some("batch"));
+        assert_eq!(environ.get("OPTS").map(String::as_str), Some("a=b"));
+        assert_eq!(environ.get("EMPTY").map(String::as_str), Some(""));
+        assert_eq!(environ.len(), 3);
+    }
+
+    /// Tests that real and effective IDs and supplementary groups are parsed from a status
+    /// file, and that a UID without a name is shown as the number instead of as root.
+    #[test]
+    fn test_parse_credentials() {
+        let status = "Name:\tsudo\nPPid:\t812\nUid:\t1000\t0\t0\t0\nGid:\t1000\t1000\t1000\t1000\nGroups:\t10 100 \n";
+        let credentials = parse_credentials(status).unwrap();
+        assert_eq!(
+            credentials,
+            Credentials {
+                uid: 1000,
+                euid: 0,
+                gid: 1000,
+                egid: 1000,
+                groups: vec![10, 100],
+            }
+        );
+        assert_eq!(parse_ppid(status), Some(812));
+        assert!(parse_credentials("Uid:\t1000\t1000\t1000\t1000\n").is_none());
+
+        let unmapped = Credentials {
+            uid: 3_000_000_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            ProcessInfo::new(-1, Vec::new(), unmapped).owner(),
+            "3000000000"
+        );
+    }
+
+    /// Tests that the executable of the current process can be resolved through a
+    /// `ProcessInfo`, and that the process is not taken for a kernel thread.
+    #[test]
+    fn test_process_info_exe() {
+        let info = ProcessInfo::read(std::process::id() as i32).unwrap();
+        assert_eq!(info.exe(), std::env::current_exe().ok().as_deref());
+        assert!(!info.kernel_thread);
+    }
+
+    /// Tests that an identity only opens for the start time the process was discovered with,
+    /// and stops being current once the process exited.
+    #[test]
+    fn test_process_identity() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        let starttime = get_starttime_for_pid(pid).unwrap();
+
+        assert!(ProcessIdentity::open(pid, starttime + 1).is_none());
+        let identity = ProcessIdentity::open(pid, starttime).unwrap();
+        assert!(identity.is_current());
+        let thread = ProcessIdentity::thread(pid, starttime);
+        assert!(thread.is_current());
+        assert!(!ProcessIdentity::thread(pid, starttime + 1).is_current());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!identity.is_current());
+        assert!(!thread.is_current());
+        assert!(ProcessIdentity::open(pid, starttime).is_none());
+    }
+}
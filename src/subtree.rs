@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ProcessConfig;
+use crate::trace;
+
+/// Keeps track of the process subtrees of rules with `scope: subtree`.
+///
+/// Every subtree has a root, the process the rule matched. All of its descendants are
+/// members of the subtree. A subtree is released as soon as its root exits; its remaining
+/// members keep their settings but are no longer tracked.
+#[derive(Debug, Default)]
+pub struct SubtreeTracker<'a> {
+    /// The rule of every subtree, keyed by the PID of its root.
+    roots: HashMap<i32, &'a ProcessConfig>,
+    /// The root of the subtree every member belongs to, keyed by the PID of the member.
+    members: HashMap<i32, i32>,
+}
+
+impl<'a> SubtreeTracker<'a> {
+    /// Creates an empty `SubtreeTracker`.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `SubtreeTracker`.
+    pub fn new() -> Self {
+        SubtreeTracker::default()
+    }
+
+    /// Starts a subtree at a process matched by a rule with `scope: subtree`. If the process
+    /// already was a member of another subtree, it now only belongs to its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the root process.
+    /// * `rule` - The rule that matched the root.
+    pub fn add_root(&mut self, pid: i32, rule: &'a ProcessConfig) {
+        trace!(
+            "PID {} is the root of a subtree for rule '{}'",
+            pid,
+            rule.name
+        );
+        self.roots.insert(pid, rule);
+        self.members.insert(pid, pid);
+    }
+
+    /// Adds a process to the subtree of its parent, if the parent is a member of one.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `ppid` - The PID of its parent.
+    ///
+    /// # Returns
+    ///
+    /// * `Some((root, rule))` with the root and rule of the subtree the process joined.
+    /// * `None` if the parent is not in a subtree.
+    pub fn join(&mut self, pid: i32, ppid: i32) -> Option<(i32, &'a ProcessConfig)> {
+        let root = *self.members.get(&ppid)?;
+        let rule = *self.roots.get(&root)?;
+        self.members.insert(pid, root);
+        Some((root, rule))
+    }
+
+    /// Returns the subtree a process is a member of.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    ///
+    /// # Returns
+    ///
+    /// * `Some((root, rule))` with the root and rule of the subtree.
+    /// * `None` if the process is not in a subtree.
+    pub fn subtree_of(&self, pid: i32) -> Option<(i32, &'a ProcessConfig)> {
+        let root = *self.members.get(&pid)?;
+        self.roots.get(&root).map(|rule| (root, *rule))
+    }
+
+    /// Forgets a process that exited, or whose PID now belongs to a different process.
+    /// If it was the root of a subtree, the whole subtree is released.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    pub fn exit(&mut self, pid: i32) {
+        self.members.remove(&pid);
+        if let Some(rule) = self.roots.remove(&pid) {
+            trace!(
+                "Root {} of the subtree for rule '{}' exited, releasing it",
+                pid,
+                rule.name
+            );
+            self.members.retain(|_, root| *root != pid);
+        }
+    }
+
+    /// Forgets all processes that are no longer running, releasing the subtrees whose root
+    /// is gone.
+    ///
+    /// # Arguments
+    ///
+    /// * `running` - The PIDs of all currently running processes.
+    pub fn retain(&mut self, running: &HashSet<i32>) {
+        let gone: Vec<i32> = self
+            .members
+            .keys()
+            .filter(|pid| !running.contains(pid))
+            .copied()
+            .collect();
+        for pid in gone {
+            self.exit(pid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that children of members join the subtree and unrelated processes do not.
+    #[test]
+    fn test_join_descendants() {
+        let rule = ProcessConfig::default();
+        let mut subtrees = SubtreeTracker::new();
+
+        subtrees.add_root(10, &rule);
+        assert_eq!(subtrees.join(11, 10).map(|(root, _)| root), Some(10));
+        assert_eq!(subtrees.join(12, 11).map(|(root, _)| root), Some(10));
+        assert!(subtrees.join(20, 1).is_none());
+        assert!(subtrees.subtree_of(20).is_none());
+    }
+
+    /// Tests that the exit of a member only removes the member, while the exit of the root
+    /// releases the whole subtree.
+    #[test]
+    fn test_exit_releases_subtree() {
+        let rule = ProcessConfig::default();
+        let mut subtrees = SubtreeTracker::new();
+
+        subtrees.add_root(10, &rule);
+        subtrees.join(11, 10);
+        subtrees.join(12, 11);
+
+        subtrees.exit(11);
+        assert!(subtrees.subtree_of(11).is_none());
+        assert!(subtrees.subtree_of(12).is_some());
+
+        subtrees.exit(10);
+        assert!(subtrees.subtree_of(12).is_none());
+        assert!(subtrees.join(13, 12).is_none());
+    }
+
+    /// Tests that a member matched by a subtree rule of its own starts a nested subtree
+    /// that outlives the outer one.
+    #[test]
+    fn test_nested_subtree() {
+        let outer = ProcessConfig::default();
+        let inner = ProcessConfig::default();
+        let mut subtrees = SubtreeTracker::new();
+
+        subtrees.add_root(10, &outer);
+        subtrees.join(11, 10);
+        subtrees.add_root(11, &inner);
+        subtrees.join(12, 11);
+
+        subtrees.retain(&HashSet::from([11, 12]));
+        assert_eq!(subtrees.subtree_of(12).map(|(root, _)| root), Some(11));
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::process::Fingerprint;
+use crate::trace;
+
+/// Describes how a process changed since it was last observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The PID was not known before, or it now belongs to a different process.
+    New,
+    /// The process `exec`ed into a different program. Carries the new exec generation.
+    Exec(u64),
+    /// The process still runs the same program.
+    Unchanged,
+}
+
+/// The state kept for a single known process.
+#[derive(Debug)]
+struct TrackedProcess {
+    /// The start time of the process, used to detect PID reuse.
+    starttime: u64,
+    /// The number of times the process has `exec`ed since it was first seen.
+    generation: u64,
+    /// The last observed fingerprint, `None` if it could not be read.
+    fingerprint: Option<Fingerprint>,
+}
+
+/// Keeps track of the exec generation of every known process.
+///
+/// A process is identified by its PID and start time. Whenever it turns into a different
+/// program its generation is bumped, so the caller knows it has to be matched again.
+#[derive(Debug, Default)]
+pub struct ProcessTracker {
+    processes: HashMap<i32, TrackedProcess>,
+}
+
+impl ProcessTracker {
+    /// Creates an empty `ProcessTracker`.
+    ///
+    /// # Returns
+    ///
+    /// * A new instance of `ProcessTracker`.
+    pub fn new() -> Self {
+        ProcessTracker::default()
+    }
+
+    /// Records a process seen while polling `/proc`.
+    /// A changed fingerprint of a known process is treated as an exec. If the fingerprint of
+    /// a known process was not known yet, it is recorded without reporting a change.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `starttime` - The start time of the process.
+    /// * `fingerprint` - The fingerprint of the program the process is running.
+    ///
+    /// # Returns
+    ///
+    /// * The `Change` since the process was last observed.
+    pub fn observe(&mut self, pid: i32, starttime: u64, fingerprint: Fingerprint) -> Change {
+        match self.processes.get_mut(&pid) {
+            Some(tracked) if tracked.starttime == starttime => {
+                let Some(known) = &tracked.fingerprint else {
+                    tracked.fingerprint = Some(fingerprint);
+                    return Change::Unchanged;
+                };
+                if *known == fingerprint {
+                    return Change::Unchanged;
+                }
+                tracked.fingerprint = Some(fingerprint);
+                tracked.generation += 1;
+                trace!(
+                    "PID {} is now in exec generation {}",
+                    pid,
+                    tracked.generation
+                );
+                Change::Exec(tracked.generation)
+            }
+            _ => {
+                self.insert(pid, starttime, Some(fingerprint));
+                Change::New
+            }
+        }
+    }
+
+    /// Records a process reported by a fork event.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the new process.
+    /// * `starttime` - The start time of the new process.
+    /// * `fingerprint` - The fingerprint of the new process, if it could be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Change::New`, a forked process is always new.
+    pub fn fork(&mut self, pid: i32, starttime: u64, fingerprint: Option<Fingerprint>) -> Change {
+        self.insert(pid, starttime, fingerprint);
+        Change::New
+    }
+
+    /// Records a process reported by an exec event.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    /// * `starttime` - The start time of the process.
+    /// * `fingerprint` - The fingerprint of the new program, if it could be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Change::Exec` with the new generation if the process was known.
+    /// * `Change::New` if the process was not known or its PID was reused.
+    pub fn exec(&mut self, pid: i32, starttime: u64, fingerprint: Option<Fingerprint>) -> Change {
+        match self.processes.get_mut(&pid) {
+            Some(tracked) if tracked.starttime == starttime => {
+                tracked.fingerprint = fingerprint;
+                tracked.generation += 1;
+                trace!(
+                    "PID {} is now in exec generation {}",
+                    pid,
+                    tracked.generation
+                );
+                Change::Exec(tracked.generation)
+            }
+            _ => {
+                self.insert(pid, starttime, fingerprint);
+                Change::New
+            }
+        }
+    }
+
+    /// Forgets a process that exited.
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - The PID of the process.
+    pub fn exit(&mut self, pid: i32) {
+        self.processes.remove(&pid);
+    }
+
+    /// Forgets all processes that are no longer running.
+    ///
+    /// # Arguments
+    ///
+    /// * `running` - The PIDs of all currently running processes.
+    pub fn retain(&mut self, running: &HashSet<i32>) {
+        self.processes.retain(|pid, _| running.contains(pid));
+    }
+
+    /// Inserts a process with generation 0, replacing any previous state for its PID.
+    fn insert(&mut self, pid: i32, starttime: u64, fingerprint: Option<Fingerprint>) {
+        self.processes.insert(
+            pid,
+            TrackedProcess {
+                starttime,
+                generation: 0,
+                fingerprint,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper function to create a fingerprint for a command without a known executable.
+    fn fingerprint(command: &str) -> Fingerprint {
+        Fingerprint {
+            exe: None,
+            command: String::from(command),
+        }
+    }
+
+    /// Tests that polling the same program twice reports it as new once and unchanged after.
+    #[test]
+    fn test_observe_unchanged_process() {
+        let mut tracker = ProcessTracker::new();
+
+        assert_eq!(tracker.observe(10, 100, fingerprint("sh")), Change::New);
+        assert_eq!(
+            tracker.observe(10, 100, fingerprint("sh")),
+            Change::Unchanged
+        );
+    }
+
+    /// Tests that a changed fingerprint with the same start time is reported as an exec
+    /// and bumps the generation each time.
+    #[test]
+    fn test_observe_detects_exec() {
+        let mut tracker = ProcessTracker::new();
+
+        tracker.observe(10, 100, fingerprint("sh -c make"));
+        assert_eq!(
+            tracker.observe(10, 100, fingerprint("make")),
+            Change::Exec(1)
+        );
+        assert_eq!(tracker.observe(10, 100, fingerprint("cc")), Change::Exec(2));
+    }
+
+    /// Tests that a different start time for a known PID is treated as a new process,
+    /// so PID reuse is never mistaken for an exec.
+    #[test]
+    fn test_observe_detects_pid_reuse() {
+        let mut tracker = ProcessTracker::new();
+
+        tracker.observe(10, 100, fingerprint("sh"));
+        assert_eq!(tracker.observe(10, 200, fingerprint("cc")), Change::New);
+    }
+
+    /// Tests the event-driven path: fork creates a process, exec bumps its generation and
+    /// exit forgets it again.
+    #[test]
+    fn test_fork_exec_exit() {
+        let mut tracker = ProcessTracker::new();
+
+        assert_eq!(tracker.fork(10, 100, None), Change::New);
+        assert_eq!(tracker.exec(10, 100, None), Change::Exec(1));
+        tracker.exit(10);
+        assert_eq!(tracker.exec(10, 100, None), Change::New);
+    }
+
+    /// Tests that a rescan after missed events only reports processes that really `exec`ed
+    /// since their fork or exec event, and adopts fingerprints that could not be read then.
+    #[test]
+    fn test_rescan_after_events() {
+        let mut tracker = ProcessTracker::new();
+
+        tracker.fork(10, 100, Some(fingerprint("sh")));
+        tracker.fork(11, 110, None);
+        tracker.fork(12, 120, Some(fingerprint("sh")));
+        tracker.exec(12, 120, Some(fingerprint("make")));
+
+        assert_eq!(
+            tracker.observe(10, 100, fingerprint("sh")),
+            Change::Unchanged
+        );
+        assert_eq!(
+            tracker.observe(11, 110, fingerprint("cc")),
+            Change::Unchanged
+        );
+        assert_eq!(
+            tracker.observe(12, 120, fingerprint("make")),
+            Change::Unchanged
+        );
+        assert_eq!(
+            tracker.observe(10, 100, fingerprint("make")),
+            Change::Exec(1)
+        );
+    }
+}